@@ -0,0 +1,1168 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use globset::Glob;
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::error::MlwError;
+use crate::logger::{LogLevel, LogStyle};
+use crate::process::ScriptProcess;
+use crate::schedule::RestartStrategy;
+
+/// Settings read from `mlw.toml`, every field is documented in the file `--gen-config` writes.
+///
+/// Environment variables in every string, including those of `[[watch]]` targets, are
+/// expanded once the file is parsed, apart from `include` and, with `shell` on, `command`.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    #[serde(default)]
+    pub path: Vec<String>,
+    pub script_args: Option<Vec<String>>, // Added to support additional arguments
+    #[serde(
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    pub delay: Duration,
+    pub verbose: Option<bool>,
+    pub log_level: Option<LogLevel>,
+    pub ignore_pattern: Option<StringOrList>,
+    pub ignore_glob: Option<StringOrList>,
+    pub no_default_ignores: Option<bool>,
+    pub respect_gitignore: Option<bool>,
+    pub only_on_content_change: Option<bool>,
+    pub include_pattern: Option<StringOrList>,
+    pub extensions: Option<Vec<String>>,
+    pub events: Option<Vec<String>>,
+    pub script_type: Option<String>,
+    pub command: Option<String>,
+    pub command_args: Option<Vec<String>>,
+    pub commands: Option<Vec<Vec<String>>>,
+    pub pass_changed_file: Option<bool>,
+    pub changed_file_default: Option<String>,
+    pub shell: Option<bool>,
+    pub stop_timeout: Option<u64>,
+    pub run_timeout: Option<u64>,
+    pub stop_signal: Option<String>,
+    pub reload_signal: Option<String>,
+    pub clear_screen: Option<bool>,
+    pub max_restarts: Option<u32>,
+    pub exit_on_crash_loop: Option<bool>,
+    pub spawn_attempts: Option<u32>,
+    pub watch: Option<Vec<WatchTarget>>,
+    pub on_start: Option<Vec<String>>,
+    pub on_exit: Option<Vec<String>>,
+    pub run_on_start: Option<bool>,
+    pub before_restart: Option<Vec<String>>,
+    pub before_restart_parallel: Option<Vec<String>>,
+    pub after_restart: Option<Vec<String>>,
+    pub env: Option<HashMap<String, String>>,
+    pub env_file: Option<String>,
+    pub cwd: Option<String>,
+    pub run_as_uid: Option<u32>,
+    pub run_as_gid: Option<u32>,
+    pub debounce_ms: Option<u64>,
+    pub restart_strategy: Option<RestartStrategy>,
+    pub cooldown_ms: Option<u64>,
+    pub queue_restarts: Option<bool>,
+    pub stabilize_ms: Option<u64>,
+    pub ignore_during_restart_ms: Option<u64>,
+    pub wait_for_port: Option<u16>,
+    pub healthcheck: Option<Vec<String>>,
+    pub healthcheck_url: Option<String>,
+    pub healthcheck_timeout_ms: Option<u64>,
+    pub recursive: Option<bool>,
+    pub poll: Option<bool>,
+    pub poll_interval_ms: Option<u64>,
+    pub notify_desktop: Option<bool>,
+    pub prefix_output: Option<bool>,
+    pub interactive: Option<bool>,
+    pub restart_key: Option<String>,
+    pub log_file: Option<String>,
+    pub log_template: Option<String>,
+    pub log_colors: Option<HashMap<String, String>>,
+    pub timestamps: Option<bool>,
+    pub timestamp_format: Option<String>,
+    pub timestamp_utc: Option<bool>,
+    /// Config files merged beneath this one, relative to the file that lists them
+    pub include: Option<Vec<String>>,
+    /// Every file the config was read from, the including file first
+    #[serde(skip)]
+    pub config_files: Vec<PathBuf>,
+}
+
+/// A watched path with its own script, declared as a `[[watch]]` table
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct WatchTarget {
+    pub path: String,
+    /// Tag for the target's output with `prefix_output`, defaulting to its path
+    pub name: Option<String>,
+    pub color: Option<String>,
+    pub script_type: Option<String>,
+    pub script_args: Option<Vec<String>>,
+    pub recursive: Option<bool>,
+    pub debounce_ms: Option<u64>,
+}
+
+impl WatchTarget {
+    /// The path to watch, which for a glob is the directory it starts from
+    pub fn root(&self) -> String {
+        if is_glob(&self.path) {
+            glob_base(&self.path)
+        } else {
+            self.path.clone()
+        }
+    }
+
+    /// The pattern events must match, when `path` is a glob
+    pub fn glob(&self) -> Option<&str> {
+        is_glob(&self.path).then_some(self.path.as_str())
+    }
+}
+
+impl ConfigFile {
+    /// Window in which further events are coalesced, none by default with `delay = 0`
+    /// so that the first event restarts right away
+    pub(crate) fn debounce(&self) -> Duration {
+        match self.debounce_ms {
+            Some(debounce_ms) => Duration::from_millis(debounce_ms),
+            None if self.delay.is_zero() => Duration::ZERO,
+            None => Duration::from_millis(DEFAULT_DEBOUNCE_MS),
+        }
+    }
+
+    /// Minimum time a run is left alone before it may be restarted
+    pub(crate) fn cooldown(&self) -> Duration {
+        Duration::from_millis(self.cooldown_ms.unwrap_or(0))
+    }
+
+    /// The most detailed level to log, where `verbose = true` means debug
+    pub fn log_level(&self) -> LogLevel {
+        self.log_level.unwrap_or(match self.verbose {
+            Some(true) => LogLevel::Debug,
+            _ => LogLevel::Info,
+        })
+    }
+
+    /// Each target path along with whether it is watched recursively
+    pub(crate) fn watch_roots(&self) -> Vec<(String, bool)> {
+        self.targets()
+            .into_iter()
+            .map(|target| {
+                let recursive = target.recursive.or(self.recursive).unwrap_or(true);
+                (target.root(), recursive)
+            })
+            .collect()
+    }
+
+    /// The `[[watch]]` targets, or one target per flat `path` entry using the global script
+    pub fn targets(&self) -> Vec<WatchTarget> {
+        match &self.watch {
+            Some(targets) => targets.clone(),
+            None => self
+                .path
+                .iter()
+                .map(|path| WatchTarget {
+                    path: path.clone(),
+                    ..Default::default()
+                })
+                .collect(),
+        }
+    }
+}
+
+fn is_glob(path: &str) -> bool {
+    path.contains(['*', '?', '[', '{'])
+}
+
+/// The leading directories of a glob pattern, up to its first wildcard component
+fn glob_base(pattern: &str) -> String {
+    let base: PathBuf = Path::new(pattern)
+        .components()
+        .take_while(|component| !is_glob(&component.as_os_str().to_string_lossy()))
+        .collect();
+    if base.as_os_str().is_empty() {
+        ".".to_string()
+    } else {
+        base.to_string_lossy().into_owned()
+    }
+}
+
+/// Parses a duration given as seconds (`2`, `0.5`) or a human string (`500ms`, `1m`)
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    match value.parse::<f64>() {
+        Ok(secs) => Duration::try_from_secs_f64(secs).map_err(|e| e.to_string()),
+        Err(_) => humantime::parse_duration(value).map_err(|e| e.to_string()),
+    }
+}
+
+/// Reads a duration given as seconds (`2`, `0.5`) or a human string (`"500ms"`, `"1m"`)
+fn deserialize_duration<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum RawDuration {
+        Seconds(u64),
+        FractionalSeconds(f64),
+        Human(String),
+    }
+
+    match RawDuration::deserialize(deserializer)? {
+        RawDuration::Seconds(secs) => Ok(Duration::from_secs(secs)),
+        RawDuration::FractionalSeconds(secs) => {
+            Duration::try_from_secs_f64(secs).map_err(serde::de::Error::custom)
+        }
+        RawDuration::Human(value) => parse_duration(&value).map_err(serde::de::Error::custom),
+    }
+}
+
+/// Writes a duration the way `deserialize_duration` reads it back, e.g. `"1s 500ms"`
+fn serialize_duration<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.collect_str(&humantime::format_duration(*duration))
+}
+
+/// A config value that may be given as a single string or a list of strings
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum StringOrList {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl StringOrList {
+    pub fn as_slice(&self) -> &[String] {
+        match self {
+            StringOrList::One(value) => std::slice::from_ref(value),
+            StringOrList::Many(values) => values,
+        }
+    }
+
+    pub(crate) fn as_mut_slice(&mut self) -> &mut [String] {
+        match self {
+            StringOrList::One(value) => std::slice::from_mut(value),
+            StringOrList::Many(values) => values,
+        }
+    }
+}
+
+/// Delay used when running from CLI arguments alone
+pub const DEFAULT_DELAY: Duration = Duration::from_secs(2);
+
+/// Milliseconds in which events are coalesced into one restart
+const DEFAULT_DEBOUNCE_MS: u64 = 300;
+
+pub(crate) const DEFAULT_CONFIG: &str = r#"
+# Default mlw configuration file
+# Every supported key is listed below; unknown keys are rejected with an error naming them
+# $VAR and ${VAR} expand from the environment in every string value, apart from include
+# and a command run with shell = true. An unset variable is an error unless it has a
+# default, as in ${PORT:-3000}; $$ is a literal $, e.g. in an ignore_pattern regex
+# Numbers such as wait_for_port stay as written
+# Relative paths in path, cwd, env_file, log_file and ignore_glob are relative to this file's directory
+# Path(s) to watch, globs like "src/**/*.rs" watch their base directory and filter by the glob
+# Each path runs as its own process, restarted only when its own files change
+path = ["./src"]
+
+# Other config files to merge beneath this one, relative to this file (optional)
+# Settings here take precedence over the included ones, and edits to them reload too
+# include = ["../mlw.base.toml"]
+
+# Timing, applied in this order for each change:
+#   debounce_ms  events this close together are coalesced into one restart
+#   stabilize_ms the changed files must keep their size and mtime this long
+#   delay        extra wait after the burst settles, before restarting
+#   cooldown_ms  a run younger than this is left alone until it reaches that age
+# Delay before each restart, in seconds or as a string like "500ms", "2s" or "1m"
+# 0 restarts on the first event, with no debounce window unless debounce_ms is set
+delay = 2
+
+# Window (in milliseconds) in which events are coalesced (optional, 300 or 0 with delay = 0)
+# debounce_ms = 300
+
+# How debounce_ms applies to changes (optional):
+#   "debounce"  wait one window after the first change, then restart (default)
+#   "throttle"  restart on the first change, but at most once per window
+#   "immediate" restart on the first change, ignoring the window
+# restart_strategy = "throttle"
+
+# Wait (in milliseconds) for changed files to stop changing size and modification time
+# before restarting, for large files written bit by bit (optional)
+# stabilize_ms = 500
+
+# Minimum time (in milliseconds) a run stays up before it is restarted (optional)
+# cooldown_ms = 0
+
+# Let a running script finish instead of stopping it on changes, then run it once more
+# for all the changes made meanwhile, for test and lint loops (optional, defaults to false)
+# queue_restarts = true
+
+# Drop file events for this many milliseconds after each restart (optional)
+# Fixes restart loops when the script writes into a watched directory, e.g. build output
+# ignore_during_restart_ms = 1000
+
+# Seconds to wait for the script to exit before killing it (optional)
+# stop_timeout = 5
+
+# Kill a run that takes longer than this many seconds, for one-shot scripts that may hang (optional)
+# run_timeout = 60
+
+# Signal asking the script to exit: SIGTERM, SIGINT, SIGHUP, SIGQUIT, SIGUSR1, SIGUSR2 or SIGKILL (optional)
+# Ignored on Windows, where scripts are always killed
+# stop_signal = "SIGTERM"
+
+# Send this signal on changes instead of restarting, for scripts that reload themselves (optional)
+# The script is only restarted when it's no longer running. Ignored on Windows
+# reload_signal = "SIGHUP"
+
+# Wait for this TCP port to be released after stopping the script, before starting it again (optional)
+# wait_for_port = 3000
+
+# Only report a restart as successful once the script is ready (optional)
+# The command must exit 0 and the URL (plain http only) must answer with a 2xx or 3xx
+# status, retried until healthcheck_timeout_ms (default: 30000) passes. Until then the
+# after_restart hook doesn't run
+# healthcheck = ["pg_isready"]
+# healthcheck_url = "http://localhost:3000/health"
+# healthcheck_timeout_ms = 30000
+
+# Clear the terminal before each restart (optional)
+# clear_screen = false
+
+# Stop restarting after this many crashes within 10 seconds (optional)
+# max_restarts = 5
+
+# Exit with code 3 once max_restarts gives up, instead of waiting for a fix (optional)
+# Lets CI and scripts notice a script that keeps crashing
+# exit_on_crash_loop = true
+
+# Times to try starting the script, with a growing pause between tries (optional)
+# spawn_attempts = 3
+
+# Verbose logging, same as log_level = "debug"
+verbose = true
+
+# Most detailed messages to log: "error", "warn", "info", "debug" or "trace" (optional)
+# log_level = "info"
+
+# Watch subdirectories of the paths too (optional)
+# recursive = true
+
+# Poll for changes instead of using native OS events, e.g. on NFS or Docker mounts (optional)
+# poll = false
+# poll_interval_ms = 1000
+
+# Show a desktop notification when the script restarts or crashes (optional)
+# notify_desktop = false
+
+# Prefix each line of the script's output with a timestamp and [app] tag (optional)
+# prefix_output = false
+
+# Let the script read from the terminal, e.g. for a REPL (optional)
+# The script then shares mlw's process group, so Ctrl+C reaches it directly and
+# processes it spawns are not stopped along with it
+# interactive = false
+
+# Line to type into the terminal to restart every script right away (default: "rs")
+# Not read when interactive is on, an empty string turns it off
+# restart_key = "rs"
+
+# Also append mlw's logs to this file, without colors (optional)
+# Changes to it never restart a script, even when it lies in a watched path
+# log_file = "mlw.log"
+
+# Layout of mlw's log lines, with {level}, {time} and {msg} placeholders (optional)
+# log_template = "{time} [{level}] {msg}"
+
+# Start each log line with the time (default: true), ignored when log_template is set
+# timestamps = true
+# strftime-style format of {time} and of these timestamps (default: "%H:%M:%S")
+# timestamp_format = "%Y-%m-%d %H:%M:%S%.3f"
+# Use UTC instead of local time (default: false)
+# timestamp_utc = false
+
+# Color of each level's log lines, by level name (optional)
+# Colors: black, red, green, yellow, blue, magenta, cyan, white, or "bright " before one
+# [log_colors]
+# info = "cyan"
+# warn = "bright yellow"
+
+# Pattern(s) for files to ignore (optional), a string or a list
+ignore_pattern = ".*\\.git.*"
+# ignore_pattern = [".*\\.git.*", ".*\\.log$"]
+
+# Glob(s) for files to ignore, alongside the regex ignore_pattern (optional)
+# ignore_glob = ["**/target/**", "**/node_modules/**"]
+
+# Changes to hidden files and directories (.git, .env, ...), node_modules, target and
+# __pycache__ below a watched path are ignored, set this to react to them too (optional)
+# no_default_ignores = true
+
+# Skip changes to files ignored by a .gitignore, checking each one from the changed
+# file's directory up to the repository root (optional, defaults to false)
+# respect_gitignore = true
+
+# Only restart when a changed file's content differs, skipping saves that just touch it.
+# Each changed file is read and hashed, and the first change to a file always restarts
+# (optional, defaults to false)
+# only_on_content_change = true
+
+# Only restart for files matching one of these patterns (optional)
+# include_pattern = [".*\\.py$"]
+
+# Only restart for files with these extensions (optional)
+# extensions = ["js", "json"]
+
+# Kinds of file events that trigger a restart: modify, create, remove (default: all)
+# Dropping remove stops editors that save by deleting and recreating from restarting twice
+# events = ["modify", "create"]
+
+# Type of script to run (e.g. python, node, go)
+# Detected from the file extension when omitted and path points to a file
+script_type = "node"
+
+
+# Additional arguments for the script (optional)
+# script_args = ["--dev", "--watch"]
+
+# Custom command to run instead of the script type lookup (optional)
+# A command with spaces is split like a shell would, quotes included, before command_args
+# command = "deno"
+# command_args = ["run", "--allow-net"]
+# command = "node --inspect 'my server.js'"
+
+# Commands to run in order on every start and restart, instead of command (optional)
+# Each runs once the one before it succeeds, a failure stopping the restart. The last is
+# the long running script, run as written without the path and script_args. Only one
+# path may run it, other [[watch]] targets need a script_type of their own
+# commands = [["npm", "run", "generate"], ["npm", "run", "build"], ["node", "dist/server.js"]]
+
+# Pass the file whose change caused the restart to the script as its last argument, after
+# script_args and in place of the path (optional), e.g. to run only the test file just
+# saved. The first run gets changed_file_default instead, or the path when it's unset.
+# The last of commands gets the file appended
+# pass_changed_file = true
+# changed_file_default = "tests"
+
+# Run the command line through sh -c (cmd /C on Windows) for pipes, && and globs (optional)
+# The command itself is shell code, so anyone who can edit this file can run anything
+# through it: only enable this for configs you trust. The path and script_args aren't
+# added to the line but passed to the shell, for the command to use as "$1" or "$@"
+# mlw leaves $VAR in the command for the shell to expand
+# shell = true
+# command = "npm run build && npm start -- \"$@\" | tee server.log"
+
+# Working directory for the script and hooks (optional)
+# cwd = "."
+
+# User and group ids the script runs as, for dropping root in containers (optional, Unix only)
+# Hooks still run as mlw's own user, and mlw must run as root to switch to another one
+# run_as_uid = 1000
+# run_as_gid = 1000
+
+# File of KEY=VALUE lines loaded into the script's environment on every restart (optional)
+# env_file = ".env"
+
+# Commands run once when mlw starts, before the first run, and once when it shuts down (optional)
+# A failing on_start command aborts startup
+# on_start = ["npm", "run", "migrate"]
+# on_exit = ["docker", "compose", "down"]
+
+# Run the script as soon as mlw starts (default: true)
+# When false, nothing runs until the first file change; --once always runs
+# run_on_start = false
+
+# Commands run around each restart (optional)
+# A failing before_restart hook aborts the restart
+# before_restart = ["npm", "run", "build"]
+# after_restart = ["sh", "cleanup.sh"]
+
+# Independent command lines run at the same time after before_restart (optional)
+# The restart goes ahead once they've all finished, and only if every one succeeded
+# before_restart_parallel = ["npm run lint", "cargo check", "sh gen-docs.sh"]
+
+# Environment variables for the script, expanded from mlw's environment like above (optional)
+# [env]
+# PORT = "3000"
+# NODE_ENV = "development"
+# PATH = "./bin:${PATH}"
+
+# Watch targets with their own script, restarted independently (optional)
+# Replaces `path` when present; unset fields fall back to the values above
+# [[watch]]
+# path = "./backend/main.py"
+# script_type = "python"
+# With prefix_output, output lines start with [name] in this color, by default the
+# target's path and a color picked in turn for each target
+# name = "api"
+# color = "magenta"
+#
+# [[watch]]
+# path = "./frontend/server.js"
+# script_type = "node"
+# script_args = ["--port", "3000"]
+# recursive = false
+# debounce_ms = 1000
+
+# Named profiles layered over the settings above, picked with --profile (optional)
+# Tables such as [env] are merged key by key, anything else is replaced
+# [profiles.test]
+# script_type = "sh"
+# path = ["./run_tests.sh"]
+#
+# [profiles.test.env]
+# NODE_ENV = "test"
+"#;
+
+/// Event kinds accepted by `events`
+const EVENT_KINDS: [&str; 3] = ["modify", "create", "remove"];
+
+/// Profile used when none is selected with `--profile`
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Reads and parses a config file using its default profile, without validating it
+pub fn parse_config(file_path: &Path) -> Result<ConfigFile, MlwError> {
+    parse_config_profile(file_path, DEFAULT_PROFILE)
+}
+
+/// Reads and parses a config file with the named `[profiles.<name>]` table merged over
+/// its top-level settings, without validating it.
+///
+/// The default profile may be left undefined, any other must exist.
+pub fn parse_config_profile(file_path: &Path, profile: &str) -> Result<ConfigFile, MlwError> {
+    read_config_profile(file_path, profile).map_err(MlwError::ConfigParse)
+}
+
+fn read_config_profile(file_path: &Path, profile: &str) -> Result<ConfigFile> {
+    let mut config_files = Vec::new();
+    let table = read_config_table(file_path, &mut Vec::new(), &mut config_files)?;
+    let mut config = select_profile(table, profile, config_files)?;
+    // A config in the working directory already resolves against it. Otherwise its
+    // canonical directory is used, as `..` in a glob would never match an event path.
+    let in_working_dir = file_path
+        .parent()
+        .is_none_or(|dir| dir.as_os_str().is_empty());
+    let config_dir = config
+        .config_files
+        .first()
+        .and_then(|file| file.parent())
+        .map(Path::to_path_buf);
+    if let (false, Some(config_dir)) = (in_working_dir, config_dir) {
+        resolve_relative_paths(&mut config, &config_dir);
+    }
+    Ok(config)
+}
+
+/// Points the relative paths of a config at the directory of its file, so that it works
+/// the same wherever mlw is started from
+fn resolve_relative_paths(config: &mut ConfigFile, config_dir: &Path) {
+    let resolve = |path: &mut String| {
+        let relative = Path::new(path.as_str());
+        if relative.is_relative() {
+            let relative = relative.strip_prefix(".").unwrap_or(relative);
+            let resolved = if relative.as_os_str().is_empty() {
+                config_dir.to_path_buf()
+            } else {
+                config_dir.join(relative)
+            };
+            *path = resolved.to_string_lossy().into_owned();
+        }
+    };
+
+    config.path.iter_mut().for_each(resolve);
+    for target in config.watch.iter_mut().flatten() {
+        resolve(&mut target.path);
+    }
+    config.cwd.iter_mut().for_each(resolve);
+    config.env_file.iter_mut().for_each(resolve);
+    config.log_file.iter_mut().for_each(resolve);
+    // Relative globs are otherwise anchored at the working directory
+    config
+        .ignore_glob
+        .iter_mut()
+        .flat_map(StringOrList::as_mut_slice)
+        .for_each(resolve);
+}
+
+/// Parses config text that has no file of its own, such as one piped through stdin,
+/// resolving its includes against the working directory. Does not validate it.
+pub fn parse_config_str(config_str: &str, profile: &str) -> Result<ConfigFile, MlwError> {
+    read_config_str(config_str, profile).map_err(MlwError::ConfigParse)
+}
+
+fn read_config_str(config_str: &str, profile: &str) -> Result<ConfigFile> {
+    let table: toml::Table = toml::from_str(config_str).context("Failed to parse config")?;
+    let mut config_files = Vec::new();
+    let table = merge_includes(table, Path::new("."), &mut Vec::new(), &mut config_files)
+        .context("Failed to parse config")?;
+    select_profile(table, profile, config_files)
+}
+
+/// Merges the named profile over a config table and deserializes the result
+fn select_profile(
+    mut table: toml::Table,
+    profile: &str,
+    config_files: Vec<PathBuf>,
+) -> Result<ConfigFile> {
+    let mut profiles = match table.remove("profiles") {
+        Some(toml::Value::Table(profiles)) => profiles,
+        Some(_) => anyhow::bail!("Failed to parse config file: `profiles` must be a table"),
+        None => toml::Table::new(),
+    };
+    match profiles.remove(profile) {
+        Some(toml::Value::Table(selected)) => merge_tables(&mut table, selected),
+        Some(_) => anyhow::bail!("Profile `{}` must be a table", profile),
+        None if profile == DEFAULT_PROFILE => {}
+        None => {
+            let available: Vec<&str> = profiles.keys().map(String::as_str).collect();
+            anyhow::bail!(
+                "Unknown profile: {} (available: {})",
+                profile,
+                if available.is_empty() {
+                    "none".to_string()
+                } else {
+                    available.join(", ")
+                }
+            );
+        }
+    }
+
+    let mut config = ConfigFile::deserialize(table)
+        .with_context(|| format!("Failed to parse config file with profile `{}`", profile))?;
+    config.config_files = config_files;
+    interpolate_env(&mut config)?;
+    Ok(config)
+}
+
+/// Expands environment variables in every string of the config, apart from `include`,
+/// which is read before them
+fn interpolate_env(config: &mut ConfigFile) -> Result<()> {
+    let pattern = Regex::new(
+        r"\$(?:(\$)|\{([A-Za-z_][A-Za-z0-9_]*)(?::-([^}]*))?\}|([A-Za-z_][A-Za-z0-9_]*))",
+    )
+    .unwrap();
+    let expand = |value: &mut String| -> Result<()> {
+        *value = expand_env(value, &pattern)?;
+        Ok(())
+    };
+
+    // The shell expands its own variables, such as those of a `for` loop
+    let command = if config.shell.unwrap_or(false) {
+        None
+    } else {
+        config.command.as_mut()
+    };
+    let strings = [
+        command,
+        config.script_type.as_mut(),
+        config.changed_file_default.as_mut(),
+        config.stop_signal.as_mut(),
+        config.reload_signal.as_mut(),
+        config.env_file.as_mut(),
+        config.cwd.as_mut(),
+        config.healthcheck_url.as_mut(),
+        config.restart_key.as_mut(),
+        config.log_file.as_mut(),
+        config.log_template.as_mut(),
+        config.timestamp_format.as_mut(),
+    ];
+    strings.into_iter().flatten().try_for_each(expand)?;
+
+    let lists = [
+        Some(&mut config.path),
+        config.script_args.as_mut(),
+        config.command_args.as_mut(),
+        config.extensions.as_mut(),
+        config.events.as_mut(),
+        config.on_start.as_mut(),
+        config.on_exit.as_mut(),
+        config.before_restart.as_mut(),
+        config.before_restart_parallel.as_mut(),
+        config.after_restart.as_mut(),
+        config.healthcheck.as_mut(),
+    ];
+    lists.into_iter().flatten().flatten().try_for_each(expand)?;
+    config
+        .commands
+        .iter_mut()
+        .flatten()
+        .flatten()
+        .try_for_each(expand)?;
+
+    let patterns = [
+        &mut config.ignore_pattern,
+        &mut config.ignore_glob,
+        &mut config.include_pattern,
+    ];
+    patterns
+        .into_iter()
+        .flatten()
+        .flat_map(StringOrList::as_mut_slice)
+        .try_for_each(expand)?;
+
+    config
+        .env
+        .iter_mut()
+        .chain(config.log_colors.iter_mut())
+        .flatten()
+        .try_for_each(|(_, value)| expand(value))?;
+
+    for target in config.watch.iter_mut().flatten() {
+        expand(&mut target.path)?;
+        [
+            target.name.as_mut(),
+            target.color.as_mut(),
+            target.script_type.as_mut(),
+        ]
+        .into_iter()
+        .flatten()
+        .try_for_each(expand)?;
+        target
+            .script_args
+            .iter_mut()
+            .flatten()
+            .try_for_each(expand)?;
+    }
+    Ok(())
+}
+
+/// Replaces `$VAR` and `${VAR}` with the variable's value, `${VAR:-default}` with the
+/// default when it's unset and `$$` with `$`, failing on any other unset variable
+fn expand_env(value: &str, pattern: &Regex) -> Result<String> {
+    let mut expanded = String::with_capacity(value.len());
+    let mut last = 0;
+    for caps in pattern.captures_iter(value) {
+        let whole = caps.get(0).unwrap();
+        expanded.push_str(&value[last..whole.start()]);
+        last = whole.end();
+
+        if caps.get(1).is_some() {
+            expanded.push('$');
+            continue;
+        }
+        let name = caps.get(2).or(caps.get(4)).unwrap().as_str();
+        match (std::env::var(name), caps.get(3)) {
+            (Ok(var), _) => expanded.push_str(&var),
+            (Err(_), Some(default)) => expanded.push_str(default.as_str()),
+            (Err(_), None) => anyhow::bail!(
+                "Undefined environment variable in config: {} (use ${{{}:-default}} to fall back to a default)",
+                name,
+                name
+            ),
+        }
+    }
+    expanded.push_str(&value[last..]);
+    Ok(expanded)
+}
+
+/// Reads a config file as a table with the files it includes merged beneath it, in
+/// order, so that later includes and then the file itself take precedence.
+///
+/// `chain` holds the files currently being included, to reject cycles.
+fn read_config_table(
+    file_path: &Path,
+    chain: &mut Vec<PathBuf>,
+    config_files: &mut Vec<PathBuf>,
+) -> Result<toml::Table> {
+    let file_path = fs::canonicalize(file_path)
+        .with_context(|| format!("Failed to read config file: {}", file_path.display()))?;
+    if chain.contains(&file_path) {
+        let cycle: Vec<String> = chain
+            .iter()
+            .chain([&file_path])
+            .map(|path| path.display().to_string())
+            .collect();
+        anyhow::bail!("Config files include each other: {}", cycle.join(" -> "));
+    }
+
+    let config_str = fs::read_to_string(&file_path)
+        .with_context(|| format!("Failed to read config file: {}", file_path.display()))?;
+    let table = parse_config_table(&config_str, &file_path)
+        .with_context(|| format!("Failed to parse config file: {}", file_path.display()))?;
+    if !config_files.contains(&file_path) {
+        config_files.push(file_path.clone());
+    }
+
+    let dir = file_path.parent().unwrap_or(Path::new("."));
+    chain.push(file_path.clone());
+    let merged = merge_includes(table, dir, chain, config_files)
+        .with_context(|| format!("Failed to parse config file: {}", file_path.display()));
+    chain.pop();
+    merged
+}
+
+/// Parses the text of a config file into a table. The config may be embedded in a
+/// `pyproject.toml` under `[tool.mlw]`, or in a JSON file such as `package.json` under
+/// an `"mlw"` key; otherwise the whole file is the config.
+fn parse_config_table(config_str: &str, file_path: &Path) -> Result<toml::Table> {
+    let file_name = file_path.file_name().unwrap_or_default();
+    if file_path
+        .extension()
+        .is_some_and(|extension| extension == "json")
+    {
+        let mut value: serde_json::Value = serde_json::from_str(config_str)?;
+        let config = match value.get_mut("mlw") {
+            Some(config) => config.take(),
+            None if file_name == "package.json" => anyhow::bail!("No \"mlw\" key in package.json"),
+            None => value,
+        };
+        return Ok(toml::Table::deserialize(config)?);
+    }
+
+    let mut table: toml::Table = toml::from_str(config_str)?;
+    let embedded = match table.get_mut("tool") {
+        Some(toml::Value::Table(tool)) => tool.remove("mlw"),
+        _ => None,
+    };
+    match embedded {
+        Some(toml::Value::Table(config)) => Ok(config),
+        Some(_) => anyhow::bail!("`tool.mlw` must be a table"),
+        None if file_name == "pyproject.toml" => {
+            anyhow::bail!("No [tool.mlw] table in pyproject.toml")
+        }
+        None => Ok(table),
+    }
+}
+
+/// Merges the files listed under `include`, relative to `dir`, beneath `table`
+fn merge_includes(
+    table: toml::Table,
+    dir: &Path,
+    chain: &mut Vec<PathBuf>,
+    config_files: &mut Vec<PathBuf>,
+) -> Result<toml::Table> {
+    let includes = match table.get("include") {
+        Some(includes) => Vec::<String>::deserialize(includes.clone())
+            .context("`include` must be a list of paths")?,
+        None => Vec::new(),
+    };
+    let mut merged = toml::Table::new();
+    for include in includes {
+        let included = read_config_table(&dir.join(include), chain, config_files)?;
+        merge_tables(&mut merged, included);
+    }
+    merge_tables(&mut merged, table);
+    Ok(merged)
+}
+
+/// Overlays `overrides` onto `base`, merging nested tables and replacing everything else
+fn merge_tables(base: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(value)) => {
+                merge_tables(base, value)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Checks that the paths, working directory, commands, patterns and signals of a
+/// config are usable, failing with the first problem found
+pub fn validate_config(config: &ConfigFile) -> Result<(), MlwError> {
+    check_config(config)
+        .into_iter()
+        .try_for_each(|check| check.result)
+        .map_err(MlwError::from)
+}
+
+/// One item of the checklist `check_config` produces
+pub struct ConfigCheck {
+    pub description: String,
+    pub result: Result<()>,
+}
+
+/// Runs every check `validate_config` does, without stopping at the first failure
+pub fn check_config(config: &ConfigFile) -> Vec<ConfigCheck> {
+    let mut checks = Vec::new();
+    let mut check = |description: String, result: Result<()>| {
+        checks.push(ConfigCheck {
+            description,
+            result,
+        })
+    };
+
+    let targets = config.targets();
+    check(
+        "Paths to watch are configured".to_string(),
+        if targets.is_empty() {
+            Err(anyhow::anyhow!("No paths to watch"))
+        } else {
+            Ok(())
+        },
+    );
+    for target in &targets {
+        let root = target.root();
+        check(
+            format!("Path exists: {}", root),
+            if Path::new(&root).exists() {
+                Ok(())
+            } else {
+                Err(MlwError::PathNotFound(root.into()).into())
+            },
+        );
+    }
+
+    if let Some(cwd) = &config.cwd {
+        check(
+            format!("Working directory exists: {}", cwd),
+            if Path::new(cwd).is_dir() {
+                Ok(())
+            } else {
+                Err(anyhow::anyhow!("Working directory does not exist: {}", cwd))
+            },
+        );
+    }
+
+    for target in &targets {
+        let command = ScriptProcess::resolve_command(config, target).and_then(|(command, _)| {
+            let program = if config.shell.unwrap_or(false) {
+                crate::process::SHELL.0
+            } else {
+                &command
+            };
+            check_command(program, config.cwd.as_deref())?;
+            Ok(command)
+        });
+        match command {
+            Ok(command) => check(format!("Command found: {}", command), Ok(())),
+            Err(e) => check(format!("Command found for {}", target.path), Err(e)),
+        }
+    }
+
+    if let Some(commands) = &config.commands {
+        check(
+            "Commands are set".to_string(),
+            if config.command.is_some() {
+                Err(anyhow::anyhow!("Set either command or commands, not both"))
+            } else if commands.is_empty() || commands.iter().any(Vec::is_empty) {
+                Err(anyhow::anyhow!(
+                    "commands must list at least one command, none of them empty"
+                ))
+            } else if targets
+                .iter()
+                .filter(|target| target.script_type.is_none())
+                .count()
+                > 1
+            {
+                // Each would start its own copy of the same command
+                Err(anyhow::anyhow!(
+                    "commands runs a single script, so only one path may use it \
+                     (give the other [[watch]] targets a script_type)"
+                ))
+            } else {
+                Ok(())
+            },
+        );
+        // The last one is checked along with the targets that run it
+        if !config.shell.unwrap_or(false) {
+            let steps = &commands[..commands.len().saturating_sub(1)];
+            for program in steps.iter().filter_map(|step| step.first()) {
+                check(
+                    format!("Command found: {}", program),
+                    check_command(program, config.cwd.as_deref()),
+                );
+            }
+        }
+    }
+
+    for hook in config.before_restart_parallel.iter().flatten() {
+        check(
+            format!("before_restart_parallel hook parses: {}", hook),
+            crate::process::split_hook(hook).map(|_| ()),
+        );
+    }
+
+    for color in targets.iter().filter_map(|target| target.color.as_ref()) {
+        check(
+            format!("Target color is known: {}", color),
+            crate::process::parse_color(color).map(|_| ()),
+        );
+    }
+
+    if let Some(url) = &config.healthcheck_url {
+        check(
+            format!("Health check URL is valid: {}", url),
+            crate::process::parse_http_url(url).map(|_| ()),
+        );
+    }
+
+    if let Some(events) = &config.events {
+        check(
+            "Event kinds are known".to_string(),
+            match events
+                .iter()
+                .find(|event| !EVENT_KINDS.contains(&event.as_str()))
+            {
+                Some(event) => Err(anyhow::anyhow!(
+                    "Unknown event kind: {} (supported: {})",
+                    event,
+                    EVENT_KINDS.join(", ")
+                )),
+                None => Ok(()),
+            },
+        );
+    }
+
+    // Bad patterns would otherwise only surface once watching starts
+    if config.ignore_pattern.is_some() {
+        check(
+            "ignore_pattern compiles".to_string(),
+            compile_patterns(config.ignore_pattern.as_ref())
+                .map(|_| ())
+                .context("Failed to compile ignore_pattern"),
+        );
+    }
+    if config.include_pattern.is_some() {
+        check(
+            "include_pattern compiles".to_string(),
+            compile_patterns(config.include_pattern.as_ref())
+                .map(|_| ())
+                .context("Failed to compile include_pattern"),
+        );
+    }
+    if let Some(ignore_glob) = &config.ignore_glob {
+        check(
+            "ignore_glob compiles".to_string(),
+            ignore_glob
+                .as_slice()
+                .iter()
+                .try_for_each(|pattern| compile_glob(pattern).map(|_| ()))
+                .context("Failed to compile ignore_glob"),
+        );
+    }
+    for glob in targets.iter().filter_map(WatchTarget::glob) {
+        check(
+            format!("Glob compiles: {}", glob),
+            compile_glob(glob)
+                .map(|_| ())
+                .context("Failed to compile watched glob"),
+        );
+    }
+
+    if config.log_template.is_some()
+        || config.log_colors.is_some()
+        || config.timestamp_format.is_some()
+    {
+        check(
+            "log_template, log_colors and timestamp_format are valid".to_string(),
+            LogStyle::from_config(config).map(|_| ()),
+        );
+    }
+
+    if config.run_as_uid.is_some() || config.run_as_gid.is_some() {
+        check(
+            "Script can run as run_as_uid and run_as_gid".to_string(),
+            check_run_as(config),
+        );
+    }
+
+    #[cfg(unix)]
+    for (name, signal) in [
+        ("stop_signal", &config.stop_signal),
+        ("reload_signal", &config.reload_signal),
+    ] {
+        if let Some(signal) = signal {
+            check(
+                format!("{} is known: {}", name, signal),
+                crate::process::parse_signal(signal).map(|_| ()),
+            );
+        }
+    }
+
+    checks
+}
+
+/// Whether mlw may switch the script to the configured user and group, which takes
+/// root unless they're already mlw's own
+#[cfg(unix)]
+fn check_run_as(config: &ConfigFile) -> Result<()> {
+    // SAFETY: geteuid and getegid can't fail and have no side effects
+    let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+    if uid != 0
+        && (config.run_as_uid.is_some_and(|run_as| run_as != uid)
+            || config.run_as_gid.is_some_and(|run_as| run_as != gid))
+    {
+        anyhow::bail!(
+            "Running the script as another user or group needs mlw to run as root (uid {})",
+            uid
+        );
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_run_as(_config: &ConfigFile) -> Result<()> {
+    anyhow::bail!("run_as_uid and run_as_gid are only supported on Unix")
+}
+
+/// Compiles regex patterns, failing on the first invalid one
+pub(crate) fn compile_patterns(patterns: Option<&StringOrList>) -> Result<Vec<Regex>> {
+    patterns
+        .map(StringOrList::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .map(|pattern| Regex::new(pattern).with_context(|| format!("Invalid pattern: {}", pattern)))
+        .collect()
+}
+
+/// Compiles a glob, anchoring relative patterns to the current directory since event
+/// paths are absolute
+pub(crate) fn compile_glob(pattern: &str) -> Result<Glob> {
+    let pattern = pattern.strip_prefix("./").unwrap_or(pattern);
+    let pattern = if Path::new(pattern).is_absolute() {
+        pattern.to_string()
+    } else {
+        let cwd = fs::canonicalize(".").context("Failed to read current directory")?;
+        cwd.join(pattern).to_string_lossy().into_owned()
+    };
+    Glob::new(&pattern).with_context(|| format!("Invalid glob: {}", pattern))
+}
+
+/// Fails early with a friendly message when a command can't be found on PATH
+fn check_command(command: &str, cwd: Option<&str>) -> Result<()> {
+    let cwd = match cwd {
+        Some(cwd) => PathBuf::from(cwd),
+        None => std::env::current_dir().context("Failed to read current directory")?,
+    };
+    which::which_in(command, std::env::var_os("PATH"), cwd)
+        .map(|_| ())
+        .map_err(|_| anyhow::anyhow!("Command `{}` not found; is it installed?", command))
+}
+
+/// Writes the documented default config, refusing to replace an existing file unless forced
+pub fn generate_default_config(output_path: &Path, force: bool) -> Result<(), MlwError> {
+    let exists = output_path.exists();
+    if exists && !force {
+        return Err(anyhow::anyhow!(
+            "Config file already exists at {:?} (use --force to overwrite)",
+            output_path
+        )
+        .into());
+    }
+
+    fs::write(output_path, DEFAULT_CONFIG).context("Failed to write config file")?;
+    if exists {
+        println!(
+            "Existing config file at {:?} overwritten with the default configuration",
+            output_path
+        );
+    } else {
+        println!("Default configuration file generated at {:?}", output_path);
+    }
+
+    Ok(())
+}
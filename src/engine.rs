@@ -0,0 +1,1626 @@
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::{self, BufRead, IsTerminal, Write},
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{Context, Result};
+use globset::{GlobMatcher, GlobSet, GlobSetBuilder};
+use ignore::gitignore::Gitignore;
+use ignore::Match;
+use notify::event::{CreateKind, ModifyKind, RenameMode};
+use notify::{Event, EventKind};
+use regex::Regex;
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::config::{compile_glob, compile_patterns, ConfigFile, StringOrList};
+use crate::error::MlwError;
+use crate::events::{self, WatchEvent};
+use crate::handler::WatchHandler;
+use crate::logger::{self, log, verbose_log, LogLevel, LogStyle};
+use crate::notifier;
+use crate::process::{
+    event_kind_name, run_hook, run_hooks_parallel, script_label, wait_until_healthy, Change,
+    ScriptProcess,
+};
+use crate::runner::{CommandRunner, SystemRunner};
+use crate::schedule::RestartSchedule;
+use crate::watcher::{FileWatcher, ReceivedEvent};
+
+#[cfg(unix)]
+use crate::process::parse_signal;
+
+/// Directories whose changes are ignored unless `no_default_ignores` is set, alongside
+/// hidden files and directories such as `.git`
+const DEFAULT_IGNORED_DIRS: [&str; 3] = ["node_modules", "target", "__pycache__"];
+
+/// Line typed into the terminal to restart every script when `restart_key` is unset
+const DEFAULT_RESTART_KEY: &str = "rs";
+
+/// Exit code for giving up on a crash loop with `exit_on_crash_loop`
+pub const EXIT_CRASH_LOOP: i32 = 3;
+
+/// Produces a fresh config when the config file changes
+type ConfigLoader = Box<dyn Fn() -> Result<ConfigFile>>;
+
+/// Watches the configured paths and restarts their scripts when files change
+pub struct Watcher {
+    config: ConfigFile,
+    dry_run: bool,
+    banner: bool,
+    /// The config file, watched to reload the config when it changes, and how to load it
+    reload: Option<(PathBuf, ConfigLoader)>,
+    /// Spawns the scripts, taken when they first start
+    runner: Option<Box<dyn CommandRunner>>,
+    /// Stops watching once Ctrl+C is pressed, set up only when asked for
+    handle_ctrlc: bool,
+    stop: StopHandle,
+}
+
+/// Stops a running [`Watcher`] from another thread, see [`Watcher::stop_handle`]
+#[derive(Clone, Debug, Default)]
+pub struct StopHandle(Arc<AtomicBool>);
+
+impl StopHandle {
+    /// Makes `run` or `run_once` stop the scripts, run `on_exit` and return 0
+    pub fn stop(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+impl Watcher {
+    /// Creates a watcher for an already validated config
+    pub fn new(config: ConfigFile) -> Self {
+        Self {
+            config,
+            dry_run: false,
+            banner: false,
+            reload: None,
+            runner: None,
+            handle_ctrlc: false,
+            stop: StopHandle::default(),
+        }
+    }
+
+    /// Logs the commands that would run instead of running them
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Logs the version, what is watched and the options set when starting
+    pub fn banner(mut self, banner: bool) -> Self {
+        self.banner = banner;
+        self
+    }
+
+    /// Stops watching on Ctrl+C, which installs the process-wide Ctrl+C handler and so
+    /// may only be enabled for one watcher per process
+    pub fn handle_ctrlc(mut self, handle_ctrlc: bool) -> Self {
+        self.handle_ctrlc = handle_ctrlc;
+        self
+    }
+
+    /// A handle stopping the watcher once it runs, e.g. from the embedding program's
+    /// own signal handling
+    pub fn stop_handle(&self) -> StopHandle {
+        self.stop.clone()
+    }
+
+    /// Starts the scripts with `runner` instead of spawning them directly
+    pub fn runner(mut self, runner: impl CommandRunner + 'static) -> Self {
+        self.runner = Some(Box::new(runner));
+        self
+    }
+
+    /// Reloads the config with `load` whenever the file at `path` changes, restarting
+    /// every script. A failing `load` keeps the current config.
+    pub fn reload_on_change(
+        mut self,
+        path: impl Into<PathBuf>,
+        load: impl Fn() -> Result<ConfigFile> + 'static,
+    ) -> Self {
+        self.reload = Some((path.into(), Box::new(load)));
+        self
+    }
+
+    /// Logs what would be watched, resolved the same way `run` resolves it: each root
+    /// directory with the glob its events must match, the config files and the filters
+    /// applied to every event
+    pub fn list_watched(&self) -> Result<(), MlwError> {
+        let runtime = Runtime::new(self.config.clone())?;
+        let config = &runtime.config;
+        let list = |label: &str, items: Vec<String>| {
+            if !items.is_empty() {
+                log(LogLevel::Info, &format!("{}: {}", label, items.join(", ")));
+            }
+        };
+
+        for ((target, root), (_, recursive)) in config
+            .targets()
+            .iter()
+            .zip(&runtime.target_roots)
+            .zip(config.watch_roots())
+        {
+            let mut line = format!("Watching {}", root.display());
+            if !recursive {
+                line.push_str(" (not recursive)");
+            }
+            if let Some(glob) = target.glob() {
+                line.push_str(&format!(", matching {}", glob));
+            }
+            log(LogLevel::Info, &line);
+        }
+        list(
+            "Config files",
+            self.config_files(config)
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect(),
+        );
+        list(
+            "Ignore patterns",
+            runtime
+                .ignore_patterns
+                .iter()
+                .map(|pattern| pattern.as_str().to_string())
+                .collect(),
+        );
+        list(
+            "Ignore globs",
+            config
+                .ignore_glob
+                .as_ref()
+                .map(|globs| globs.as_slice().to_vec())
+                .unwrap_or_default(),
+        );
+        if !config.no_default_ignores.unwrap_or(false) {
+            list(
+                "Ignored by default",
+                ["hidden files".to_string()]
+                    .into_iter()
+                    .chain(DEFAULT_IGNORED_DIRS.map(String::from))
+                    .collect(),
+            );
+        }
+        if config.respect_gitignore.unwrap_or(false) {
+            log(LogLevel::Info, "Ignoring files matched by .gitignore");
+        }
+        list(
+            "Include patterns",
+            runtime
+                .include_patterns
+                .iter()
+                .map(|pattern| pattern.as_str().to_string())
+                .collect(),
+        );
+        list("Extensions", runtime.extensions.clone());
+        list("Events", config.events.clone().unwrap_or_default());
+        Ok(())
+    }
+
+    /// The config file and the files it includes, empty unless reloading on change
+    fn config_files(&self, config: &ConfigFile) -> Vec<PathBuf> {
+        let Some(config_file) = self
+            .reload
+            .as_ref()
+            .and_then(|(path, _)| fs::canonicalize(path).ok())
+        else {
+            return Vec::new();
+        };
+        let mut config_files = vec![config_file];
+        for included in &config.config_files {
+            if !config_files.contains(included) {
+                config_files.push(included.clone());
+            }
+        }
+        config_files
+    }
+
+    /// Starts the scripts and restarts them on changes, until the watcher fails or is
+    /// stopped through a [`StopHandle`] or, with `handle_ctrlc`, by Ctrl+C.
+    ///
+    /// Returns the code to exit with when watching stops on its own, which is
+    /// [`EXIT_CRASH_LOOP`] when `exit_on_crash_loop` gave up on a crashing script.
+    ///
+    /// `handler` is called back on changes, restarts and crashes, see [`WatchHandler`].
+    pub fn run(mut self, mut handler: impl WatchHandler) -> Result<i32, MlwError> {
+        let initial_run = self.config.run_on_start.unwrap_or(true);
+        let (mut runtime, script_process) = self.start(initial_run)?;
+        let config = &runtime.config;
+
+        let (tx, mut rx) = channel();
+        let poll_interval = config.poll.unwrap_or(false).then(|| {
+            Duration::from_millis(config.poll_interval_ms.unwrap_or(DEFAULT_POLL_INTERVAL_MS))
+        });
+        let mut file_watcher = FileWatcher::new(tx, poll_interval)?;
+        for (path, recursive) in config.watch_roots() {
+            file_watcher.watch(Path::new(&path), recursive)?;
+        }
+
+        for (path, _) in config.watch_roots() {
+            verbose_log(
+                LogLevel::Debug,
+                &format!("Watching path: {}", path),
+                config.log_level(),
+            );
+        }
+
+        let mut config_files = self.config_files(config);
+        watch_config_files(&config_files, &runtime, &mut file_watcher)?;
+        if self.reload.is_some() {
+            handle_reload_signal()?;
+        }
+
+        // Reading the terminal would steal input meant for an interactive script
+        let restart_key = runtime
+            .config
+            .restart_key
+            .as_deref()
+            .unwrap_or(DEFAULT_RESTART_KEY);
+        let manual_restarts = (!runtime.config.interactive.unwrap_or(false)
+            && !restart_key.is_empty()
+            && io::stdin().is_terminal())
+        .then(|| read_restart_requests(restart_key.to_string()));
+        if manual_restarts.is_some() {
+            verbose_log(
+                LogLevel::Debug,
+                &format!("Type `{}` and Enter to restart", restart_key),
+                runtime.config.log_level(),
+            );
+        }
+
+        let mut lost_roots = Vec::new();
+        let mut pending = PendingChanges::default();
+        let result = loop {
+            let received = rx.recv_timeout(pending.timeout());
+            script_process
+                .lock()
+                .unwrap()
+                .kill_timed_out(&runtime.config);
+            script_process
+                .lock()
+                .unwrap()
+                .reap_exited(&runtime.config, &mut handler);
+            if runtime.config.exit_on_crash_loop.unwrap_or(false)
+                && script_process.lock().unwrap().gave_up()
+            {
+                log(LogLevel::Error, "Exiting because the script keeps crashing");
+                break Ok(EXIT_CRASH_LOOP);
+            }
+            if self.stop.is_stopped() {
+                log(LogLevel::Info, "Shutting down...");
+                break Ok(0);
+            }
+            recover_lost_roots(&runtime, &mut file_watcher, &mut lost_roots, &mut pending);
+
+            match received {
+                Ok((_, Ok(event))) => {
+                    // Files written by a freshly started script would otherwise restart it again
+                    let quiet =
+                        Duration::from_millis(runtime.config.ignore_during_restart_ms.unwrap_or(0));
+                    let last_restart = script_process.lock().unwrap().last_restart;
+                    if last_restart.is_some_and(|last_restart| last_restart.elapsed() < quiet) {
+                        verbose_log(
+                            LogLevel::Trace,
+                            &format!("Ignoring event right after restart: {:?}", event.paths),
+                            runtime.config.log_level(),
+                        );
+                    } else {
+                        pending.add(&event, &runtime, &config_files);
+                    }
+                }
+                // A vanished path is picked up by the lost roots check, and a new watcher
+                // would hit the same watch limit, anything else may have killed the watcher
+                Ok((_, Err(e))) => match e.kind {
+                    notify::ErrorKind::PathNotFound | notify::ErrorKind::WatchNotFound => {
+                        verbose_log(
+                            LogLevel::Warn,
+                            &format!("File watcher error: {}", e),
+                            runtime.config.log_level(),
+                        )
+                    }
+                    notify::ErrorKind::MaxFilesWatch => log(
+                        LogLevel::Error,
+                        &format!(
+                            "Too many files to watch, some changes will be missed. \
+                             Raise the OS limit or narrow the watched paths: {}",
+                            e
+                        ),
+                    ),
+                    _ => {
+                        log(
+                            LogLevel::Error,
+                            &format!("File watcher error, recreating it: {}", e),
+                        );
+                        match reopen_watcher(&mut file_watcher) {
+                            Ok(reopened) => rx = reopened,
+                            Err(e) => break Err(e),
+                        }
+                    }
+                },
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    log(LogLevel::Error, "File watcher stopped, recreating it");
+                    match reopen_watcher(&mut file_watcher) {
+                        Ok(reopened) => rx = reopened,
+                        Err(e) => break Err(e),
+                    }
+                }
+            }
+
+            // A successful reload restarts everything, covering any other pending changes
+            let config_changed = pending.take_config();
+            let signalled = take_reload_signal();
+            if let (true, Some((_, load))) = (config_changed || signalled, &self.reload) {
+                if config_changed {
+                    events::emit(WatchEvent::FileChanged {
+                        paths: config_files.clone(),
+                        config: true,
+                    });
+                }
+                match reload_config(
+                    load.as_ref(),
+                    signalled,
+                    &mut runtime,
+                    &mut file_watcher,
+                    &mut script_process.lock().unwrap(),
+                ) {
+                    Ok(true) => {
+                        // Includes may have been added or removed
+                        config_files = self.config_files(&runtime.config);
+                        if let Err(e) =
+                            watch_config_files(&config_files, &runtime, &mut file_watcher)
+                        {
+                            log(
+                                LogLevel::Error,
+                                &format!("Failed to watch included config files: {:#}", e),
+                            );
+                        }
+                        pending = PendingChanges::default();
+                        rx.try_iter().for_each(drop);
+                        continue;
+                    }
+                    Ok(false) => {}
+                    Err(e) => log(
+                        LogLevel::Error,
+                        &format!("Failed to reload config, keeping the previous one: {:#}", e),
+                    ),
+                }
+            }
+
+            // Typed restarts skip the debounce window and cover whatever was pending
+            if manual_restarts
+                .as_ref()
+                .is_some_and(|requests| requests.try_iter().count() > 0)
+            {
+                let config = &runtime.config;
+                if let Err(e) = handle_change(
+                    config,
+                    &mut script_process.lock().unwrap(),
+                    &mut handler,
+                    None,
+                    None,
+                ) {
+                    log(LogLevel::Error, &format!("Error handling change: {}", e));
+                }
+                runtime.restarted(None);
+                rx.try_iter().for_each(drop);
+                pending = PendingChanges::default();
+                continue;
+            }
+
+            // Restart everything once, dropping the backlog of events the restart covers.
+            // Which files changed is unknown, so the scripts aren't told of any.
+            if pending.take_rescan() {
+                let config = &runtime.config;
+                log(
+                    LogLevel::Warn,
+                    "File events overflowed, restarting every script once",
+                );
+                rx.try_iter().for_each(drop);
+                handler.on_file_change(&[]);
+                events::emit(WatchEvent::FileChanged {
+                    paths: Vec::new(),
+                    config: false,
+                });
+                if script_process.lock().unwrap().allow_restart() {
+                    if let Err(e) = handle_change(
+                        config,
+                        &mut script_process.lock().unwrap(),
+                        &mut handler,
+                        None,
+                        None,
+                    ) {
+                        log(LogLevel::Error, &format!("Error handling change: {}", e));
+                    }
+                    runtime.restarted(None);
+                }
+                rx.try_iter().for_each(drop);
+                pending = PendingChanges::default();
+                continue;
+            }
+
+            let finished =
+                pending.take_finished(|index| script_process.lock().unwrap().is_running(index));
+            for (index, change) in finished {
+                if let Err(e) = handle_change(
+                    &runtime.config,
+                    &mut script_process.lock().unwrap(),
+                    &mut handler,
+                    Some(&[index]),
+                    Some(&change),
+                ) {
+                    log(LogLevel::Error, &format!("Error handling change: {}", e));
+                }
+                runtime.restarted(Some(&[index]));
+            }
+
+            let stabilize = runtime.config.stabilize_ms.map(Duration::from_millis);
+            for (index, burst) in pending.take_due(stabilize) {
+                let config = &runtime.config;
+                // Every path is hashed so that each one's remembered content stays current
+                if config.only_on_content_change.unwrap_or(false)
+                    && burst
+                        .paths
+                        .iter()
+                        .filter(|path| runtime.content_changed(path))
+                        .count()
+                        == 0
+                {
+                    verbose_log(
+                        LogLevel::Debug,
+                        &format!("Content unchanged: {:?}", burst.paths),
+                        config.log_level(),
+                    );
+                    continue;
+                }
+                // A capped burst may differ in the paths left out, so it never counts as one.
+                // Nor does a save made after the new run started, only late events for
+                // writes it already saw.
+                if burst.paths.len() < MAX_BURST_PATHS
+                    && runtime.schedules.borrow()[index].is_repeat(&burst.paths, burst.started)
+                    && written_before(&burst.paths, script_process.lock().unwrap().last_spawn)
+                {
+                    verbose_log(
+                        LogLevel::Debug,
+                        &format!("Same files changed again: {:?}", burst.paths),
+                        config.log_level(),
+                    );
+                    continue;
+                }
+                verbose_log(
+                    LogLevel::Debug,
+                    &format!("Coalesced changes: {:?}", burst.paths),
+                    config.log_level(),
+                );
+                handler.on_file_change(&burst.paths);
+                events::emit(WatchEvent::FileChanged {
+                    paths: burst.paths.clone(),
+                    config: false,
+                });
+
+                if !script_process.lock().unwrap().allow_restart() {
+                    verbose_log(
+                        LogLevel::Warn,
+                        "Ignoring event while the script is crash looping",
+                        config.log_level(),
+                    );
+                    continue;
+                }
+
+                // Let the current run finish, following up with a single run for however
+                // many changes arrive in the meantime
+                if config.queue_restarts.unwrap_or(false)
+                    && script_process.lock().unwrap().is_running(index)
+                {
+                    if !pending.queued.contains_key(&index) {
+                        verbose_log(
+                            LogLevel::Info,
+                            &format!(
+                                "{} is still running, restarting it once it exits",
+                                script_label(config, &config.targets()[index])
+                            ),
+                            config.log_level(),
+                        );
+                    }
+                    pending.queued.insert(index, burst.change);
+                    continue;
+                }
+
+                if let Err(e) = handle_change(
+                    config,
+                    &mut script_process.lock().unwrap(),
+                    &mut handler,
+                    Some(&[index]),
+                    Some(&burst.change),
+                ) {
+                    log(LogLevel::Error, &format!("Error handling change: {}", e));
+                }
+                runtime.schedules.borrow_mut()[index].restarted_for(Instant::now(), &burst.paths);
+
+                // Events received before the new run was spawned describe changes it already
+                // sees, but those for other targets still count. Later ones, e.g. saved while
+                // a healthcheck or after_restart hook ran, restart it again.
+                let last_spawn = script_process.lock().unwrap().last_spawn;
+                let (before, after): (Vec<_>, Vec<_>) = rx
+                    .try_iter()
+                    .partition(|(received, _)| last_spawn.is_some_and(|spawn| *received < spawn));
+                for (_, event) in before {
+                    if let Ok(event) = event {
+                        pending.add(&event, &runtime, &config_files);
+                    }
+                }
+                if let Some(skipped) = pending.targets.remove(&index) {
+                    verbose_log(
+                        LogLevel::Debug,
+                        &format!(
+                            "Ignoring {} change(s) received before the restart",
+                            skipped.paths.len()
+                        ),
+                        config.log_level(),
+                    );
+                }
+                for (_, event) in after {
+                    if let Ok(event) = event {
+                        pending.add(&event, &runtime, &config_files);
+                    }
+                }
+            }
+        };
+
+        script_process.lock().unwrap().stop(None, &runtime.config);
+        run_on_exit(&runtime.config, self.dry_run);
+        result.map_err(MlwError::from)
+    }
+
+    /// Runs the scripts once without watching, returning the first failing exit code
+    /// or 0 once all of them have exited or the watcher is stopped
+    pub fn run_once(mut self) -> Result<i32, MlwError> {
+        let (runtime, script_process) = self.start(true)?;
+        let code = loop {
+            let mut script_process = script_process.lock().unwrap();
+            if self.stop.is_stopped() {
+                log(LogLevel::Info, "Shutting down...");
+                script_process.stop(None, &runtime.config);
+                break 0;
+            }
+            script_process.kill_timed_out(&runtime.config);
+            if let Some(code) = script_process.try_wait_all() {
+                script_process.flush_output();
+                break code;
+            }
+            drop(script_process);
+            std::thread::sleep(EXIT_POLL_INTERVAL);
+        };
+        run_on_exit(&runtime.config, self.dry_run);
+        Ok(code)
+    }
+
+    /// Runs `on_start` and, with `initial_run`, the first run of every script
+    fn start(&mut self, initial_run: bool) -> Result<(Runtime, Arc<Mutex<ScriptProcess>>)> {
+        if self.handle_ctrlc {
+            let stop = self.stop.clone();
+            ctrlc::set_handler(move || stop.stop()).context("Failed to install Ctrl+C handler")?;
+        }
+        let runtime = Runtime::new(self.config.clone())?;
+        let config = &runtime.config;
+
+        if let Some(log_file) = &config.log_file {
+            logger::set_log_file(Path::new(log_file))?;
+        }
+        apply_log_style(config)?;
+
+        verbose_log(LogLevel::Debug, "Configuration loaded.", config.log_level());
+        if self.banner {
+            log_banner(config);
+        }
+
+        if let Some(on_start) = &config.on_start {
+            run_hook("on_start", on_start, config, self.dry_run)?;
+        }
+
+        let runner = self.runner.take().unwrap_or_else(|| Box::new(SystemRunner));
+        let script_process = Arc::new(Mutex::new(ScriptProcess::new(self.dry_run, runner)));
+        if initial_run {
+            script_process.lock().unwrap().restart(config, None, None)?;
+        } else {
+            log(LogLevel::Info, "Waiting for a change before the first run");
+        }
+
+        Ok((runtime, script_process))
+    }
+}
+
+/// Logs a summary of the setup, so that shared output shows what mlw was doing. The
+/// options line lists every other setting given, except `env`, which may hold secrets.
+fn log_banner(config: &ConfigFile) {
+    log(
+        LogLevel::Info,
+        &format!("mlw {}", env!("CARGO_PKG_VERSION")),
+    );
+    let scripts: Vec<String> = config
+        .targets()
+        .iter()
+        .map(|target| script_label(config, target))
+        .collect();
+    log(LogLevel::Info, &format!("Running: {}", scripts.join(", ")));
+    log(
+        LogLevel::Info,
+        &format!(
+            "Timing: delay {}, debounce {}",
+            humantime::format_duration(config.delay),
+            humantime::format_duration(config.debounce())
+        ),
+    );
+
+    const SHOWN: [&str; 8] = [
+        "path",
+        "watch",
+        "script_type",
+        "command",
+        "commands",
+        "delay",
+        "debounce_ms",
+        "env",
+    ];
+    let Ok(serde_json::Value::Object(settings)) = serde_json::to_value(config) else {
+        return;
+    };
+    let options: Vec<String> = settings
+        .iter()
+        .filter(|(key, value)| !value.is_null() && !SHOWN.contains(&key.as_str()))
+        .map(|(key, value)| match value {
+            serde_json::Value::String(value) => format!("{}={}", key, value),
+            value => format!("{}={}", key, value),
+        })
+        .collect();
+    if !options.is_empty() {
+        log(LogLevel::Info, &format!("Options: {}", options.join(", ")));
+    }
+}
+
+/// Resolves an event path the same way target roots are, so globs and roots line up
+fn absolute_path(path: &Path) -> PathBuf {
+    fs::canonicalize(path)
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Whether every one of the files was last modified before `spawn`, false for any that
+/// is gone or has no modification time
+fn written_before(paths: &[PathBuf], spawn: Option<Instant>) -> bool {
+    let Some(spawn) = spawn.and_then(|spawn| SystemTime::now().checked_sub(spawn.elapsed())) else {
+        return false;
+    };
+    paths.iter().all(|path| {
+        fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .is_ok_and(|modified| modified < spawn)
+    })
+}
+
+/// Resolves a file that may not exist yet through its directory, which does
+fn canonical_file(path: &Path) -> PathBuf {
+    match (path.parent(), path.file_name()) {
+        (Some(dir), Some(name)) => {
+            let dir = if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            };
+            absolute_path(dir).join(name)
+        }
+        _ => absolute_path(path),
+    }
+}
+
+/// The loaded config along with everything derived from it at startup
+struct Runtime {
+    config: ConfigFile,
+    ignore_patterns: Vec<Regex>,
+    include_patterns: Vec<Regex>,
+    extensions: Vec<String>,
+    ignore_globs: GlobSet,
+    /// Canonical root of each target, used to route events to the target they belong to
+    target_roots: Vec<PathBuf>,
+    /// Pattern each target's events must match, for targets given as a glob
+    target_globs: Vec<Option<GlobMatcher>>,
+    /// When each target's changes are due, from its debounce window or the global one
+    schedules: RefCell<Vec<RestartSchedule>>,
+    /// Parsed `.gitignore` of each directory looked at, `None` when it has none
+    gitignores: RefCell<HashMap<PathBuf, Option<Gitignore>>>,
+    /// Hash of each file's content when it last changed, for `only_on_content_change`
+    content_hashes: RefCell<HashMap<PathBuf, u64>>,
+    /// Canonical path of `log_file`, whose writes mustn't restart a script watching it
+    log_file: Option<PathBuf>,
+}
+
+impl Runtime {
+    fn new(config: ConfigFile) -> Result<Self> {
+        let ignore_patterns = compile_patterns(config.ignore_pattern.as_ref())
+            .context("Failed to compile ignore_pattern")?;
+        let include_patterns = compile_patterns(config.include_pattern.as_ref())
+            .context("Failed to compile include_pattern")?;
+        // Accept both "rs" and ".rs", matching case-insensitively
+        let extensions = config
+            .extensions
+            .iter()
+            .flatten()
+            .map(|ext| ext.trim_start_matches('.').to_lowercase())
+            .collect();
+        let mut ignore_globs = GlobSetBuilder::new();
+        for pattern in config
+            .ignore_glob
+            .as_ref()
+            .map(StringOrList::as_slice)
+            .unwrap_or_default()
+        {
+            ignore_globs.add(compile_glob(pattern).context("Failed to compile ignore_glob")?);
+        }
+        let ignore_globs = ignore_globs
+            .build()
+            .context("Failed to compile ignore_glob")?;
+
+        let targets = config.targets();
+        let target_roots = targets
+            .iter()
+            .map(|target| {
+                let root = target.root();
+                fs::canonicalize(&root).unwrap_or_else(|_| root.into())
+            })
+            .collect();
+        let target_globs = targets
+            .iter()
+            .map(|target| {
+                target
+                    .glob()
+                    .map(|glob| Ok(compile_glob(glob)?.compile_matcher()))
+                    .transpose()
+            })
+            .collect::<Result<_>>()?;
+        let strategy = config.restart_strategy.unwrap_or_default();
+        let schedules = targets
+            .iter()
+            .map(|target| {
+                let window = target
+                    .debounce_ms
+                    .map(Duration::from_millis)
+                    .unwrap_or(config.debounce());
+                RestartSchedule::new(strategy, window)
+            })
+            .collect();
+        let log_file = config
+            .log_file
+            .as_deref()
+            .map(|log_file| canonical_file(Path::new(log_file)));
+        Ok(Self {
+            config,
+            ignore_patterns,
+            include_patterns,
+            extensions,
+            ignore_globs,
+            target_roots,
+            target_globs,
+            schedules: RefCell::new(schedules),
+            gitignores: RefCell::default(),
+            content_hashes: RefCell::default(),
+            log_file,
+        })
+    }
+
+    /// Whether the path is the `log_file`, which every log line writes to
+    fn is_log_file(&self, path: &Path) -> bool {
+        self.log_file.as_deref().is_some_and(|log_file| {
+            path.file_name() == log_file.file_name() && canonical_file(path) == log_file
+        })
+    }
+
+    /// Records a restart of the given targets, or of every target when `None`
+    fn restarted(&self, targets: Option<&[usize]>) {
+        let now = Instant::now();
+        for (index, schedule) in self.schedules.borrow_mut().iter_mut().enumerate() {
+            if targets.is_none_or(|targets| targets.contains(&index)) {
+                schedule.restarted(now);
+            }
+        }
+    }
+
+    fn root_index(&self, path: &Path) -> Option<usize> {
+        // The polling watcher reports paths as configured, which may be relative
+        let path = absolute_path(path);
+        self.target_roots
+            .iter()
+            .position(|root| path.starts_with(root))
+    }
+
+    /// Whether the path is a hidden file, lies in a hidden directory or in one of
+    /// `DEFAULT_IGNORED_DIRS`, judged below the target root so that a project inside a
+    /// hidden directory still gets watched
+    fn is_default_ignored(&self, path: &Path) -> bool {
+        let path = absolute_path(path);
+        let Some(root) = self.target_roots.iter().find(|root| path.starts_with(root)) else {
+            return false;
+        };
+        path.strip_prefix(root).is_ok_and(|relative| {
+            relative.components().any(|component| match component {
+                Component::Normal(name) => {
+                    let name = name.to_string_lossy();
+                    name.starts_with('.') || DEFAULT_IGNORED_DIRS.contains(&name.as_ref())
+                }
+                _ => false,
+            })
+        })
+    }
+
+    /// Whether the closest `.gitignore` with a rule for the path ignores it, looking in
+    /// each directory from the path's own up to the repository root
+    fn is_gitignored(&self, path: &Path) -> bool {
+        let path = absolute_path(path);
+        // An edited .gitignore is parsed again the next time it's needed
+        if path.file_name().is_some_and(|name| name == ".gitignore") {
+            if let Some(dir) = path.parent() {
+                self.gitignores.borrow_mut().remove(dir);
+            }
+        }
+
+        let is_dir = path.is_dir();
+        let mut gitignores = self.gitignores.borrow_mut();
+        for dir in path.ancestors().skip(1) {
+            let gitignore = gitignores.entry(dir.to_path_buf()).or_insert_with(|| {
+                let file = dir.join(".gitignore");
+                file.is_file().then(|| Gitignore::new(file).0)
+            });
+            match gitignore
+                .as_ref()
+                .map(|gitignore| gitignore.matched_path_or_any_parents(&path, is_dir))
+            {
+                Some(Match::Ignore(_)) => return true,
+                Some(Match::Whitelist(_)) => return false,
+                _ => {}
+            }
+            if dir.join(".git").exists() {
+                break;
+            }
+        }
+        false
+    }
+
+    /// Whether the file's content differs from when it last changed, remembering its new
+    /// hash. The first change to a file after startup always counts, as do removals.
+    fn content_changed(&self, path: &Path) -> bool {
+        let Ok(content) = fs::read(path) else {
+            return true;
+        };
+        let hash = xxh3_64(&content);
+        self.content_hashes
+            .borrow_mut()
+            .insert(absolute_path(path), hash)
+            != Some(hash)
+    }
+
+    /// Whether some target's root contains the path and, for glob targets, its glob matches
+    fn matches_target_glob(&self, path: &Path) -> bool {
+        let path = absolute_path(path);
+        self.target_roots
+            .iter()
+            .zip(&self.target_globs)
+            .any(|(root, glob)| {
+                path.starts_with(root) && glob.as_ref().is_none_or(|glob| glob.is_match(&path))
+            })
+    }
+}
+
+/// File events for one target collected during its debounce window
+struct Burst {
+    /// When the window closes and the burst is handled
+    deadline: Instant,
+    /// When the first event arrived
+    started: Instant,
+    /// The first relevant change, which is what gets reported to the script
+    change: Change,
+    paths: Vec<PathBuf>,
+    /// Size and modification time of each path when the window last closed, kept
+    /// while waiting for them to settle with `stabilize_ms`
+    file_states: Option<Vec<Option<(u64, SystemTime)>>>,
+}
+
+/// Bursts still inside their debounce window, kept per target so that a change under
+/// one target neither restarts nor delays the others
+#[derive(Default)]
+struct PendingChanges {
+    targets: BTreeMap<usize, Burst>,
+    /// Deadline of a pending config file change
+    config: Option<Instant>,
+    /// Deadline of a rescan, requested when the OS dropped events
+    rescan: Option<Instant>,
+    /// Changes waiting for their target's current run to exit, with `queue_restarts`
+    queued: BTreeMap<usize, Change>,
+}
+
+impl PendingChanges {
+    fn add(&mut self, event: &Event, runtime: &Runtime, config_files: &[PathBuf]) {
+        if config_files
+            .iter()
+            .any(|config_file| is_config_event(event, config_file))
+        {
+            // Even when restarts are immediate, a half-written config isn't worth loading
+            let settle = runtime
+                .config
+                .stabilize_ms
+                .map_or(CONFIG_SETTLE_WINDOW, Duration::from_millis)
+                .max(CONFIG_SETTLE_WINDOW)
+                .max(runtime.config.debounce());
+            self.config.get_or_insert_with(|| Instant::now() + settle);
+            return;
+        }
+
+        // The event queue overflowed, so any file may have changed without an event
+        if event.need_rescan() {
+            self.rescan
+                .get_or_insert_with(|| Instant::now() + runtime.config.debounce());
+            return;
+        }
+
+        // Skip neighbours of the config file that no target watches
+        let Some(path) = relevant_path(event, runtime) else {
+            return;
+        };
+        let Some(index) = runtime.root_index(path) else {
+            return;
+        };
+        self.push(index, path, event.kind, runtime);
+    }
+
+    /// Counts a watched directory that came back towards its target's burst, so that
+    /// the events of recreating it and writing its files restart the target once
+    fn add_recovered(&mut self, index: usize, root: &Path, runtime: &Runtime) {
+        let root = absolute_path(root);
+        self.push(index, &root, EventKind::Create(CreateKind::Folder), runtime);
+    }
+
+    /// Adds a relevant change to the burst of its target, opening one if there is none
+    fn push(&mut self, index: usize, path: &Path, kind: EventKind, runtime: &Runtime) {
+        let now = Instant::now();
+        let burst = self.targets.entry(index).or_insert_with(|| Burst {
+            deadline: runtime.schedules.borrow()[index].deadline(now),
+            started: now,
+            change: Change {
+                path: path.to_path_buf(),
+                kind,
+            },
+            paths: Vec::new(),
+            file_states: None,
+        });
+
+        // Atomic saves move the file away and another into its place. Keep the window
+        // open for the second half, which then makes the pair a single modify.
+        if is_rename_away(&kind) {
+            burst.deadline = burst.deadline.max(now + RENAME_PAIR_WINDOW);
+        } else if burst.change.path == path && is_rename_away(&burst.change.kind) {
+            verbose_log(
+                LogLevel::Trace,
+                &format!("Collapsed replaced file into one modify: {:?}", path),
+                runtime.config.log_level(),
+            );
+            burst.change.kind = EventKind::Modify(ModifyKind::Any);
+        }
+        // A bulk change like a branch switch restarts once however many files it touches
+        if burst.paths.len() < MAX_BURST_PATHS && !burst.paths.iter().any(|changed| changed == path)
+        {
+            burst.paths.push(path.to_path_buf());
+        }
+    }
+
+    /// How long until the next window closes, capped so exited scripts are still noticed
+    fn timeout(&self) -> Duration {
+        self.targets
+            .values()
+            .map(|burst| burst.deadline)
+            .chain(self.config)
+            .chain(self.rescan)
+            .min()
+            .map_or(EXIT_POLL_INTERVAL, |deadline| {
+                deadline
+                    .saturating_duration_since(Instant::now())
+                    .min(EXIT_POLL_INTERVAL)
+            })
+    }
+
+    /// Whether a config change finished its window, consuming it
+    fn take_config(&mut self) -> bool {
+        let due = self
+            .config
+            .is_some_and(|deadline| deadline <= Instant::now());
+        if due {
+            self.config = None;
+        }
+        due
+    }
+
+    /// Whether a rescan finished its window, consuming it along with every target
+    /// burst, since a rescan restarts them all
+    fn take_rescan(&mut self) -> bool {
+        if self.rescan.is_none_or(|deadline| deadline > Instant::now()) {
+            return false;
+        }
+        self.targets.clear();
+        self.rescan = None;
+        true
+    }
+
+    /// Removes and returns the queued changes of targets that are no longer running
+    fn take_finished(&mut self, is_running: impl Fn(usize) -> bool) -> Vec<(usize, Change)> {
+        let finished: Vec<usize> = self
+            .queued
+            .keys()
+            .copied()
+            .filter(|index| !is_running(*index))
+            .collect();
+        finished
+            .into_iter()
+            .filter_map(|index| Some((index, self.queued.remove(&index)?)))
+            .collect()
+    }
+
+    /// Removes and returns the target bursts whose windows have closed. With a
+    /// `stabilize` wait, a burst is only due once its files kept the same size and
+    /// modification time for that long, so that large files are no longer being written.
+    fn take_due(&mut self, stabilize: Option<Duration>) -> Vec<(usize, Burst)> {
+        let now = Instant::now();
+        let due: Vec<usize> = self
+            .targets
+            .iter_mut()
+            .filter(|(_, burst)| burst.deadline <= now)
+            .filter_map(|(index, burst)| {
+                let Some(stabilize) = stabilize else {
+                    return Some(*index);
+                };
+                let file_states = burst.paths.iter().map(|path| file_state(path)).collect();
+                if burst.file_states.as_ref() == Some(&file_states) {
+                    return Some(*index);
+                }
+                burst.file_states = Some(file_states);
+                burst.deadline = now + stabilize;
+                None
+            })
+            .collect();
+        due.into_iter()
+            .filter_map(|index| Some((index, self.targets.remove(&index)?)))
+            .collect()
+    }
+}
+
+/// Size and modification time of a file, `None` once it's gone
+fn file_state(path: &Path) -> Option<(u64, SystemTime)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.len(), metadata.modified().ok()?))
+}
+
+/// Milliseconds between scans when using the polling watcher
+const DEFAULT_POLL_INTERVAL_MS: u64 = 1000;
+
+/// How often the main loop checks whether the script exited, which also bounds how
+/// precisely run times are reported
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Shortest wait before reloading a changed config file, for the editor to finish
+/// saving it, or `stabilize_ms` when that's longer
+const CONFIG_SETTLE_WINDOW: Duration = Duration::from_millis(100);
+
+/// How long a burst stays open after a file was moved away, for the file replacing it
+const RENAME_PAIR_WINDOW: Duration = Duration::from_millis(50);
+
+/// Changed paths kept per burst, beyond which further paths are only counted as one burst
+const MAX_BURST_PATHS: usize = 1000;
+
+/// Attempts at recreating a failed file watcher before mlw exits
+const WATCHER_REOPEN_ATTEMPTS: u32 = 5;
+
+/// Pause between attempts at recreating the file watcher
+const WATCHER_REOPEN_DELAY: Duration = Duration::from_secs(1);
+
+/// Switches log lines to the configured template and colors
+fn apply_log_style(config: &ConfigFile) -> Result<()> {
+    let style = LogStyle::from_config(config)?;
+    logger::set_style(style);
+    Ok(())
+}
+
+fn clear_screen() {
+    // Only clear real terminals so piped output and NO_COLOR users are left alone
+    if !io::stdout().is_terminal() || std::env::var_os("NO_COLOR").is_some() {
+        return;
+    }
+    print!("\x1B[2J\x1B[H");
+    let _ = io::stdout().flush();
+}
+
+/// Runs the `on_exit` command, logging rather than returning its failure since mlw is exiting anyway
+fn run_on_exit(config: &ConfigFile, dry_run: bool) {
+    if let Some(on_exit) = &config.on_exit {
+        if let Err(e) = run_hook("on_exit", on_exit, config, dry_run) {
+            log(LogLevel::Error, &format!("{:#}", e));
+        }
+    }
+}
+
+fn handle_change(
+    config: &ConfigFile,
+    script_process: &mut ScriptProcess,
+    handler: &mut dyn WatchHandler,
+    targets: Option<&[usize]>,
+    change: Option<&Change>,
+) -> Result<()> {
+    if config.clear_screen.unwrap_or(false) {
+        clear_screen();
+    }
+    verbose_log(
+        LogLevel::Info,
+        match change {
+            Some(_) => "File change detected. Restarting...",
+            None => "Manual restart requested. Restarting...",
+        },
+        config.log_level(),
+    );
+    if let (true, Some(change)) = (script_process.dry_run, change) {
+        log(
+            LogLevel::Info,
+            &format!(
+                "[dry-run] {} of {:?} would trigger a restart",
+                event_kind_name(&change.kind),
+                change.path
+            ),
+        );
+    }
+    logger::countdown(config.delay);
+
+    // Let the previous run live for at least the cooldown before replacing it
+    if let Some(last_restart) = script_process.last_restart {
+        let remaining = config.cooldown().saturating_sub(last_restart.elapsed());
+        if !remaining.is_zero() {
+            verbose_log(
+                LogLevel::Debug,
+                &format!("Waiting {:?} for the restart cooldown", remaining),
+                config.log_level(),
+            );
+            std::thread::sleep(remaining);
+        }
+    }
+
+    let all_targets = config.targets();
+    let label = match (targets, all_targets.as_slice()) {
+        (Some(targets), _) => targets
+            .iter()
+            .map(|index| script_label(config, &all_targets[*index]))
+            .collect::<Vec<_>>()
+            .join(", "),
+        (None, [only]) => script_label(config, only),
+        (None, _) => "All scripts".to_string(),
+    };
+
+    if let Some(hook) = &config.before_restart {
+        run_hook("before_restart", hook, config, script_process.dry_run)?;
+    }
+    if let Some(hooks) = &config.before_restart_parallel {
+        run_hooks_parallel("before_restart", hooks, config, script_process.dry_run)?;
+    }
+    events::emit(WatchEvent::RestartStarted {
+        script: label.clone(),
+        path: change.map(|change| change.path.clone()).unwrap_or_default(),
+    });
+
+    // Reload-capable scripts get a signal instead of a restart, as long as they're alive
+    #[cfg(unix)]
+    let reloaded = match &config.reload_signal {
+        Some(name) => script_process.signal_running(config, targets, parse_signal(name)?),
+        None => false,
+    };
+    #[cfg(not(unix))]
+    let reloaded = false;
+
+    if reloaded {
+        verbose_log(
+            LogLevel::Info,
+            &format!(
+                "Sent {} to {}",
+                config.reload_signal.as_deref().unwrap_or_default(),
+                label
+            ),
+            config.log_level(),
+        );
+    } else {
+        script_process.restart(config, targets, change)?;
+    }
+    wait_until_healthy(config, script_process.dry_run)?;
+    if !reloaded {
+        verbose_log(
+            LogLevel::Info,
+            "script restarted successfully.",
+            config.log_level(),
+        );
+    }
+    if config.notify_desktop.unwrap_or(false) && !script_process.dry_run {
+        notifier::notify("mlw: script restarted", &format!("{} restarted", label));
+    }
+    handler.on_restart(&label);
+    events::emit(WatchEvent::RestartSucceeded { script: label });
+    if let Some(hook) = &config.after_restart {
+        run_hook("after_restart", hook, config, script_process.dry_run)?;
+    }
+    Ok(())
+}
+
+fn should_ignore_path(path: &Path, ignore_patterns: &[Regex]) -> bool {
+    let path = path.to_string_lossy();
+    ignore_patterns.iter().any(|regex| regex.is_match(&path))
+}
+
+/// Paths always pass when no include patterns are configured
+fn should_watch_path(path: &Path, include_patterns: &[Regex]) -> bool {
+    let path = path.to_string_lossy();
+    include_patterns.is_empty() || include_patterns.iter().any(|regex| regex.is_match(&path))
+}
+
+/// Paths always pass when no extensions are configured
+fn has_watched_extension(path: &Path, extensions: &[String]) -> bool {
+    extensions.is_empty()
+        || path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .is_some_and(|ext| extensions.contains(&ext))
+}
+
+/// Watches the directories of the config files so that edits, including atomic saves,
+/// reload the config. Skips directories a target already covers, to avoid watching
+/// them twice.
+fn watch_config_files(
+    config_files: &[PathBuf],
+    runtime: &Runtime,
+    file_watcher: &mut FileWatcher,
+) -> Result<()> {
+    for config_dir in config_files.iter().filter_map(|file| file.parent()) {
+        let covered = runtime
+            .target_roots
+            .iter()
+            .zip(runtime.config.watch_roots())
+            .any(|(root, (_, recursive))| {
+                root == config_dir || (recursive && config_dir.starts_with(root))
+            });
+        if !covered {
+            file_watcher.watch(config_dir, false)?;
+        }
+    }
+    Ok(())
+}
+
+/// Set by the SIGHUP handler, asking the main loop to reload the config
+#[cfg(unix)]
+static RELOAD_SIGNALLED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Reloads the config on SIGHUP from then on, instead of exiting
+#[cfg(unix)]
+fn handle_reload_signal() -> Result<()> {
+    extern "C" fn on_sighup(_: libc::c_int) {
+        RELOAD_SIGNALLED.store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+    let previous = unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = on_sighup as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(libc::SIGHUP, &action, std::ptr::null_mut())
+    };
+    if previous != 0 {
+        return Err(io::Error::last_os_error()).context("Failed to install SIGHUP handler");
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn handle_reload_signal() -> Result<()> {
+    Ok(())
+}
+
+/// Whether SIGHUP arrived since the last call
+fn take_reload_signal() -> bool {
+    #[cfg(unix)]
+    return RELOAD_SIGNALLED.swap(false, std::sync::atomic::Ordering::Relaxed);
+    #[cfg(not(unix))]
+    false
+}
+
+/// Reads lines typed into the terminal on a background thread, sending a request
+/// whenever one matches `restart_key`
+fn read_restart_requests(restart_key: String) -> Receiver<()> {
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if line.trim() == restart_key && tx.send(()).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+/// Recreates the file watcher after it failed, retrying a few times before giving up
+fn reopen_watcher(file_watcher: &mut FileWatcher) -> Result<Receiver<ReceivedEvent>> {
+    let mut attempt = 1;
+    loop {
+        let (tx, rx) = channel();
+        match file_watcher.reopen(tx) {
+            Ok(()) => {
+                log(LogLevel::Info, "File watcher recreated, watching again");
+                return Ok(rx);
+            }
+            Err(e) if attempt < WATCHER_REOPEN_ATTEMPTS => {
+                log(
+                    LogLevel::Warn,
+                    &format!(
+                        "Failed to recreate file watcher (attempt {}/{}): {:#}",
+                        attempt, WATCHER_REOPEN_ATTEMPTS, e
+                    ),
+                );
+                std::thread::sleep(WATCHER_REOPEN_DELAY);
+                attempt += 1;
+            }
+            Err(e) => return Err(e.context("File watcher could not be recreated")),
+        }
+    }
+}
+
+fn is_config_event(event: &Event, config_file: &Path) -> bool {
+    // Access events come from mlw reading the file itself
+    if matches!(event.kind, EventKind::Access(_)) {
+        return false;
+    }
+    event.paths.iter().any(|path| {
+        path.file_name() == config_file.file_name()
+            && fs::canonicalize(path).is_ok_and(|path| path == config_file)
+    })
+}
+
+/// Re-reads the config after the config file changed, or when `signalled` by SIGHUP,
+/// re-establishing watches and restarting the script.
+///
+/// Returns whether it restarted, which an unchanged config only does when signalled.
+/// On error the current config is left in place.
+fn reload_config(
+    load: &dyn Fn() -> Result<ConfigFile>,
+    signalled: bool,
+    runtime: &mut Runtime,
+    file_watcher: &mut FileWatcher,
+    script_process: &mut ScriptProcess,
+) -> Result<bool> {
+    let config = load()?;
+    if config == runtime.config && !signalled {
+        verbose_log(
+            LogLevel::Debug,
+            "Config file changed on disk but its contents are the same",
+            runtime.config.log_level(),
+        );
+        return Ok(false);
+    }
+    let new_runtime = Runtime::new(config)?;
+    apply_log_style(&new_runtime.config)?;
+    if new_runtime.log_file != runtime.log_file {
+        match &new_runtime.config.log_file {
+            Some(log_file) => logger::set_log_file(Path::new(log_file))?,
+            None => logger::close_log_file(),
+        }
+    }
+
+    let old_roots = runtime.config.watch_roots();
+    let new_roots = new_runtime.config.watch_roots();
+    for (path, _) in old_roots.iter().filter(|root| !new_roots.contains(root)) {
+        file_watcher.unwatch(Path::new(path))?;
+    }
+    for (path, recursive) in new_roots.iter().filter(|root| !old_roots.contains(root)) {
+        file_watcher.watch(Path::new(path), *recursive)?;
+        verbose_log(
+            LogLevel::Info,
+            &format!("Watching path: {}", path),
+            new_runtime.config.log_level(),
+        );
+    }
+
+    *runtime = new_runtime;
+    log(
+        LogLevel::Info,
+        if signalled {
+            "Config reloaded on SIGHUP. Restarting..."
+        } else {
+            "Config file changed, reloaded. Restarting..."
+        },
+    );
+    script_process.restart(&runtime.config, None, None)?;
+    Ok(true)
+}
+
+/// Notices watched directories that were deleted, e.g. by a branch switch, and watches
+/// them again once they reappear, restarting their targets to pick up what changed
+fn recover_lost_roots(
+    runtime: &Runtime,
+    file_watcher: &mut FileWatcher,
+    lost_roots: &mut Vec<PathBuf>,
+    pending: &mut PendingChanges,
+) {
+    let config = &runtime.config;
+    for path in file_watcher.lost() {
+        if !lost_roots.contains(&path) {
+            verbose_log(
+                LogLevel::Warn,
+                &format!(
+                    "Watched path was removed, waiting for it to return: {}",
+                    path.display()
+                ),
+                config.log_level(),
+            );
+            lost_roots.push(path);
+        }
+    }
+
+    lost_roots.retain(|path| {
+        if !path.exists() {
+            return true;
+        }
+        match file_watcher.rewatch(path) {
+            Ok(()) => {
+                verbose_log(
+                    LogLevel::Info,
+                    &format!(
+                        "Watched path is back, watching it again: {}",
+                        path.display()
+                    ),
+                    config.log_level(),
+                );
+                if let Some(index) = runtime.root_index(path) {
+                    pending.add_recovered(index, path, runtime);
+                }
+                false
+            }
+            Err(e) => {
+                verbose_log(
+                    LogLevel::Debug,
+                    &format!("{:#}, retrying", e),
+                    config.log_level(),
+                );
+                true
+            }
+        }
+    });
+}
+
+/// The path an event is about, which for a rename reported as one event is where the
+/// file ended up
+fn event_path(event: &Event) -> Option<&Path> {
+    match event.kind {
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => event.paths.last(),
+        _ => event.paths.first(),
+    }
+    .map(PathBuf::as_path)
+}
+
+/// Whether an event takes a file away from its path, as the first half of a replace
+fn is_rename_away(kind: &EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From))
+    )
+}
+
+/// Applies the ignore, include, extension and event kind filters to an event,
+/// returning the changed path if it should trigger a restart
+fn relevant_path<'a>(event: &'a Event, runtime: &Runtime) -> Option<&'a Path> {
+    let config = &runtime.config;
+    let path = event_path(event)?;
+
+    // Not even logged at trace level, as that would write to the file again
+    if runtime.is_log_file(path) {
+        return None;
+    }
+
+    if should_ignore_path(path, &runtime.ignore_patterns) {
+        verbose_log(
+            LogLevel::Trace,
+            &format!("Ignored file: {:?}", path),
+            config.log_level(),
+        );
+        return None;
+    }
+
+    if !config.no_default_ignores.unwrap_or(false) && runtime.is_default_ignored(path) {
+        verbose_log(
+            LogLevel::Trace,
+            &format!("Ignored by default: {:?}", path),
+            config.log_level(),
+        );
+        return None;
+    }
+
+    if runtime.ignore_globs.is_match(absolute_path(path)) {
+        verbose_log(
+            LogLevel::Trace,
+            &format!("Ignored file: {:?}", path),
+            config.log_level(),
+        );
+        return None;
+    }
+
+    if config.respect_gitignore.unwrap_or(false) && runtime.is_gitignored(path) {
+        verbose_log(
+            LogLevel::Trace,
+            &format!("Ignored by .gitignore: {:?}", path),
+            config.log_level(),
+        );
+        return None;
+    }
+
+    if !runtime.matches_target_glob(path) {
+        verbose_log(
+            LogLevel::Trace,
+            &format!("Not matched by path glob: {:?}", path),
+            config.log_level(),
+        );
+        return None;
+    }
+
+    if !should_watch_path(path, &runtime.include_patterns) {
+        verbose_log(
+            LogLevel::Trace,
+            &format!("Not included: {:?}", path),
+            config.log_level(),
+        );
+        return None;
+    }
+
+    if !has_watched_extension(path, &runtime.extensions) {
+        verbose_log(
+            LogLevel::Trace,
+            &format!("Unwatched extension: {:?}", path),
+            config.log_level(),
+        );
+        return None;
+    }
+
+    if !matches!(
+        event.kind,
+        EventKind::Modify(_) | EventKind::Create(_) | EventKind::Remove(_)
+    ) {
+        return None;
+    }
+
+    let kind = event_kind_name(&event.kind);
+    if config
+        .events
+        .as_ref()
+        .is_some_and(|events| !events.iter().any(|event| event == kind))
+    {
+        verbose_log(
+            LogLevel::Trace,
+            &format!("Ignored {} event: {:?}", kind, path),
+            config.log_level(),
+        );
+        return None;
+    }
+
+    Some(path)
+}
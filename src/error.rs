@@ -0,0 +1,64 @@
+//! Errors returned by the public API, for callers that handle some kinds themselves
+
+use std::path::PathBuf;
+
+/// What went wrong loading a config or watching with it.
+///
+/// Failures without a kind of their own, such as an invalid ignore pattern or a
+/// failing hook, are kept as `Other` with their full context.
+#[derive(thiserror::Error, Debug)]
+pub enum MlwError {
+    /// The config couldn't be read, its TOML or its includes are invalid, or the
+    /// selected profile doesn't exist
+    #[error(transparent)]
+    ConfigParse(anyhow::Error),
+
+    /// A path to watch doesn't exist
+    #[error("Path does not exist: {}", .0.display())]
+    PathNotFound(PathBuf),
+
+    /// The script could not be started, even after retrying
+    #[error("Failed to start {command} after {attempts} attempt(s)")]
+    SpawnFailed {
+        command: String,
+        attempts: u32,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// The script could not be started as the configured user or group
+    #[error("Failed to start {command} with run_as_uid {uid:?} and run_as_gid {gid:?}")]
+    RunAsFailed {
+        command: String,
+        uid: Option<u32>,
+        gid: Option<u32>,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// No script type by this name, `supported` lists the ones there are
+    #[error("Unsupported script type: {name} (supported: {})", supported.join(", "))]
+    UnsupportedScriptType {
+        name: String,
+        supported: Vec<&'static str>,
+    },
+
+    /// The file watcher refused a path
+    #[error("Failed to watch path: {}", path.display())]
+    WatchFailed {
+        path: PathBuf,
+        #[source]
+        source: notify::Error,
+    },
+
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+/// Recovers the kind of an error raised inside mlw, which passes errors around as
+/// `anyhow::Error` internally
+impl From<anyhow::Error> for MlwError {
+    fn from(error: anyhow::Error) -> Self {
+        error.downcast::<MlwError>().unwrap_or_else(MlwError::Other)
+    }
+}
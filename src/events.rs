@@ -0,0 +1,101 @@
+//! Lifecycle events, delivered to in-process subscribers and to clients of the
+//! events socket as newline-delimited JSON
+
+use anyhow::Result;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+
+#[cfg(unix)]
+use std::{
+    io::Write,
+    os::unix::net::{UnixListener, UnixStream},
+};
+
+/// Something that happened while watching, serialized with its kind under `event`
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum WatchEvent {
+    /// Changes settled after the debounce window, `config` when it was the config file
+    FileChanged {
+        paths: Vec<PathBuf>,
+        config: bool,
+    },
+    RestartStarted {
+        script: String,
+        path: PathBuf,
+    },
+    RestartSucceeded {
+        script: String,
+    },
+    Crash {
+        script: String,
+        status: String,
+    },
+}
+
+/// Receivers handed out by `subscribe`, dropped once they hang up
+static SUBSCRIBERS: Mutex<Vec<Sender<WatchEvent>>> = Mutex::new(Vec::new());
+
+/// Clients connected to the events socket, dropped once a write to them fails
+#[cfg(unix)]
+static CLIENTS: Mutex<Vec<UnixStream>> = Mutex::new(Vec::new());
+
+/// Returns a channel receiving every following event
+pub fn subscribe() -> Receiver<WatchEvent> {
+    let (tx, rx) = channel();
+    SUBSCRIBERS.lock().unwrap().push(tx);
+    rx
+}
+
+/// Opens a Unix socket that streams every following event as a line of JSON
+#[cfg(unix)]
+pub fn listen(path: &Path) -> Result<()> {
+    use anyhow::Context;
+
+    // A socket left behind by a previous run would make bind fail
+    if path.exists() {
+        std::fs::remove_file(path)
+            .with_context(|| format!("Failed to remove stale socket: {}", path.display()))?;
+    }
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("Failed to open events socket: {}", path.display()))?;
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // A client that stops reading is dropped rather than stalling mlw
+            let _ = stream.set_write_timeout(Some(std::time::Duration::from_millis(100)));
+            CLIENTS.lock().unwrap().push(stream);
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn listen(_path: &Path) -> Result<()> {
+    anyhow::bail!("--events-socket is only supported on Unix")
+}
+
+/// Sends an event to every subscriber and connected client
+pub(crate) fn emit(event: WatchEvent) {
+    #[cfg(unix)]
+    {
+        let mut record = serde_json::to_value(&event).unwrap_or_default();
+        if let Some(record) = record.as_object_mut() {
+            let timestamp =
+                humantime::format_rfc3339_millis(std::time::SystemTime::now()).to_string();
+            record.insert("ts".to_string(), timestamp.into());
+        }
+        let line = format!("{}\n", record);
+        CLIENTS
+            .lock()
+            .unwrap()
+            .retain_mut(|client| client.write_all(line.as_bytes()).is_ok());
+    }
+
+    SUBSCRIBERS
+        .lock()
+        .unwrap()
+        .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+}
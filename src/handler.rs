@@ -0,0 +1,18 @@
+use std::path::PathBuf;
+
+/// Rust callbacks for lifecycle events, passed to `Watcher::run`.
+///
+/// Every method defaults to doing nothing, so implementors only override the events
+/// they care about. `()` is the handler that ignores everything.
+pub trait WatchHandler {
+    /// Changes under a target settled after its debounce window, before it restarts
+    fn on_file_change(&mut self, _paths: &[PathBuf]) {}
+
+    /// A script was restarted, or signalled with `reload_signal`
+    fn on_restart(&mut self, _script: &str) {}
+
+    /// A script exited unsuccessfully, `status` being e.g. "exit code 1"
+    fn on_crash(&mut self, _script: &str, _status: &str) {}
+}
+
+impl WatchHandler for () {}
@@ -0,0 +1,46 @@
+//! The watch and restart engine behind the `mlw` command line tool, for embedding it
+//! in other programs.
+//!
+//! ```no_run
+//! use std::path::Path;
+//!
+//! let config = mlw::parse_config(Path::new("mlw.toml"))?;
+//! mlw::validate_config(&config)?;
+//!
+//! // Follow restarts and crashes from another thread, or implement `WatchHandler`
+//! // to receive them as callbacks
+//! let events = mlw::events::subscribe();
+//! std::thread::spawn(move || {
+//!     for event in events {
+//!         println!("{:?}", event);
+//!     }
+//! });
+//!
+//! mlw::Watcher::new(config).run(())?;
+//! # Ok::<(), mlw::MlwError>(())
+//! ```
+
+mod config;
+mod engine;
+mod error;
+pub mod events;
+mod handler;
+pub mod logger;
+mod notifier;
+mod process;
+mod runner;
+mod schedule;
+mod watcher;
+
+pub use config::{
+    check_config, generate_default_config, parse_config, parse_config_profile, parse_config_str,
+    parse_duration, validate_config, ConfigCheck, ConfigFile, StringOrList, WatchTarget,
+    DEFAULT_DELAY, DEFAULT_PROFILE,
+};
+pub use engine::{StopHandle, Watcher, EXIT_CRASH_LOOP};
+pub use error::MlwError;
+pub use events::WatchEvent;
+pub use handler::WatchHandler;
+pub use process::SCRIPT_TYPES;
+pub use runner::{CommandRunner, SystemRunner};
+pub use schedule::RestartStrategy;
@@ -1,31 +1,331 @@
+use anyhow::{Context, Result};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{Local, Utc};
 use colored::*;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant, SystemTime};
 
-/// Enum representing the log levels
+use crate::config::ConfigFile;
 
+/// File that log lines are also appended to, opened by `set_log_file`
+static LOG_FILE: Mutex<Option<File>> = Mutex::new(None);
+
+/// How console log lines are written, set once by `set_format`
+static FORMAT: OnceLock<LogFormat> = OnceLock::new();
+
+/// Template and colors of log lines, the built-in look until `set_style` is called
+static STYLE: RwLock<Option<LogStyle>> = RwLock::new(None);
+
+/// Look of log lines before a config sets one
+static DEFAULT_STYLE: OnceLock<LogStyle> = OnceLock::new();
+
+/// Templates used without a `log_template`, depending on `timestamps`
+const TIMESTAMPED_LOG_TEMPLATE: &str = "{time} [{level}] {msg}";
+const PLAIN_LOG_TEMPLATE: &str = "[{level}] {msg}";
+
+/// Timestamp format used without a `timestamp_format`, e.g. `14:03:27`
+const DEFAULT_TIME_FORMAT: &str = "%H:%M:%S";
+
+/// Whether only errors reach the console, set by `set_quiet`
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// How often the restart countdown redraws
+const COUNTDOWN_TICK: Duration = Duration::from_millis(100);
+
+/// Console output format for mlw's own messages
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Colored, human readable lines
+    #[default]
+    Text,
+    /// One JSON object per line on stdout
+    Json,
+}
+
+/// Enum representing the log levels, ordered from least to most detailed
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
 pub enum LogLevel {
-    Info,
     Error,
+    Warn,
+    Info,
     Debug,
+    Trace,
 }
 
-pub fn verbose_log(level: LogLevel, message: &str, verbose: Option<bool>) {
-    match verbose {
-        Some(true) => {
-            log(level, message);
-        }
-        _ => {
-            match level {
-                LogLevel::Info | LogLevel::Error => log(level, message),
-                _ => {} // Skip Debug level logs if not verbose
-            }
-        }
+/// Logs the message if its level is at or below the configured threshold
+pub fn verbose_log(level: LogLevel, message: &str, max_level: LogLevel) {
+    if level <= max_level {
+        log(level, message);
     }
 }
 /// Logs messages to the console
 pub fn log(level: LogLevel, message: &str) {
+    let style = STYLE.read().unwrap();
+    let style = style.as_ref().unwrap_or_else(|| {
+        DEFAULT_STYLE.get_or_init(|| {
+            LogStyle::from_config(&ConfigFile::default()).expect("default log style is valid")
+        })
+    });
+    let line = style.render(level, message);
+
+    // Quiet mode only affects the console, the log file keeps the full record
+    if QUIET.load(Ordering::Relaxed) && level != LogLevel::Error {
+        write_log_file(level, message);
+        return;
+    }
+
+    if FORMAT.get() == Some(&LogFormat::Json) {
+        let timestamp = humantime::format_rfc3339_millis(SystemTime::now()).to_string();
+        let record = serde_json::json!({ "level": level, "ts": timestamp, "msg": message });
+        println!("{}", record);
+        write_log_file(level, message);
+        return;
+    }
+
+    let color = style.colors.get(&level).copied();
+    let colored = match (color, level) {
+        (Some(color), _) => line.color(color),
+        (None, LogLevel::Info) => line.green(),
+        (None, LogLevel::Error) => line.red(),
+        (None, LogLevel::Warn) => line.bright_yellow(),
+        (None, LogLevel::Debug) => line.yellow(),
+        (None, LogLevel::Trace) => line.dimmed(),
+    };
     match level {
-        LogLevel::Info => println!("{}", format!("[INFO] {}", message).green()),
-        LogLevel::Error => eprintln!("{}", format!("[ERROR] {}", message).red()),
-        LogLevel::Debug => println!("{}", format!("[DEBUG] {}", message).yellow()),
+        LogLevel::Error | LogLevel::Warn => eprintln!("{}", colored),
+        _ => println!("{}", colored),
+    }
+
+    write_log_file(level, message);
+}
+
+impl LogLevel {
+    /// Upper case name used in log lines, e.g. `INFO`
+    fn label(self) -> &'static str {
+        match self {
+            LogLevel::Error => "ERROR",
+            LogLevel::Warn => "WARN",
+            LogLevel::Info => "INFO",
+            LogLevel::Debug => "DEBUG",
+            LogLevel::Trace => "TRACE",
+        }
+    }
+}
+
+/// A piece of a `log_template`
+#[derive(Debug)]
+enum Segment {
+    Text(String),
+    Level,
+    Time,
+    Message,
+}
+
+/// How log lines look, from the `log_template`, `log_colors` and timestamp settings
+#[derive(Debug)]
+pub(crate) struct LogStyle {
+    template: Vec<Segment>,
+    colors: HashMap<LogLevel, Color>,
+    time_format: String,
+    utc: bool,
+}
+
+impl LogStyle {
+    /// Parses the template's `{level}`, `{time}` and `{msg}` placeholders, the colors
+    /// by level name and the strftime-style timestamp format
+    pub(crate) fn from_config(config: &ConfigFile) -> Result<Self> {
+        let template = match (&config.log_template, config.timestamps.unwrap_or(true)) {
+            (Some(template), _) => template.as_str(),
+            (None, true) => TIMESTAMPED_LOG_TEMPLATE,
+            (None, false) => PLAIN_LOG_TEMPLATE,
+        };
+        let mut segments = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .map(|end| start + end)
+                .ok_or_else(|| anyhow::anyhow!("Unclosed placeholder in log_template: {}", rest))?;
+            if start > 0 {
+                segments.push(Segment::Text(rest[..start].to_string()));
+            }
+            segments.push(match &rest[start + 1..end] {
+                "level" => Segment::Level,
+                "time" => Segment::Time,
+                "msg" => Segment::Message,
+                other => anyhow::bail!(
+                    "Unknown placeholder {{{}}} in log_template (supported: {{level}}, {{time}}, {{msg}})",
+                    other
+                ),
+            });
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest.to_string()));
+        }
+
+        let mut parsed = HashMap::new();
+        for (level, color) in config.log_colors.iter().flatten() {
+            let level = match level.as_str() {
+                "error" => LogLevel::Error,
+                "warn" => LogLevel::Warn,
+                "info" => LogLevel::Info,
+                "debug" => LogLevel::Debug,
+                "trace" => LogLevel::Trace,
+                _ => anyhow::bail!("Unknown level in log_colors: {}", level),
+            };
+            let color = color
+                .parse::<Color>()
+                .map_err(|_| anyhow::anyhow!("Unknown color in log_colors: {}", color))?;
+            parsed.insert(level, color);
+        }
+
+        let time_format = config
+            .timestamp_format
+            .as_deref()
+            .unwrap_or(DEFAULT_TIME_FORMAT);
+        if StrftimeItems::new(time_format).any(|item| item == Item::Error) {
+            anyhow::bail!("Invalid timestamp_format: {}", time_format);
+        }
+
+        Ok(Self {
+            template: segments,
+            colors: parsed,
+            time_format: time_format.to_string(),
+            utc: config.timestamp_utc.unwrap_or(false),
+        })
+    }
+
+    fn render(&self, level: LogLevel, message: &str) -> String {
+        let mut line = String::new();
+        for segment in &self.template {
+            match segment {
+                Segment::Text(text) => line.push_str(text),
+                Segment::Level => line.push_str(level.label()),
+                Segment::Time if self.utc => {
+                    line.push_str(&Utc::now().format(&self.time_format).to_string())
+                }
+                Segment::Time => line.push_str(&Local::now().format(&self.time_format).to_string()),
+                Segment::Message => line.push_str(message),
+            }
+        }
+        line
+    }
+}
+
+/// Uses `style` for every following log line
+pub(crate) fn set_style(style: LogStyle) {
+    *STYLE.write().unwrap() = Some(style);
+}
+
+/// Sleeps for the restart delay, showing a countdown on terminals so the wait doesn't
+/// look like a hang. Plain text output only, and never in quiet mode.
+pub fn countdown(delay: Duration) {
+    if delay.is_zero() {
+        return;
+    }
+    let animate = std::io::stdout().is_terminal()
+        && !QUIET.load(Ordering::Relaxed)
+        && FORMAT.get() != Some(&LogFormat::Json)
+        && delay >= COUNTDOWN_TICK * 2;
+    if !animate {
+        std::thread::sleep(delay);
+        return;
+    }
+
+    const FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+    let deadline = Instant::now() + delay;
+    let mut stdout = std::io::stdout();
+    for frame in FRAMES.iter().cycle() {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        let _ = write!(
+            stdout,
+            "\r{} Restarting in {:.1}s...",
+            frame,
+            remaining.as_secs_f64()
+        );
+        let _ = stdout.flush();
+        std::thread::sleep(remaining.min(COUNTDOWN_TICK));
+    }
+    // Erase the line so the next log starts clean
+    let _ = write!(stdout, "\r\x1b[2K");
+    let _ = stdout.flush();
+}
+
+/// Chooses the console format for every following log line
+pub fn set_format(format: LogFormat) {
+    let _ = FORMAT.set(format);
+}
+
+/// Hides everything but errors from the console, regardless of the configured level
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+/// Turns off colors when asked to, when NO_COLOR is set, or when stdout isn't a terminal
+pub fn configure_color(no_color: bool) {
+    if no_color || std::env::var_os("NO_COLOR").is_some() || !std::io::stdout().is_terminal() {
+        colored::control::set_override(false);
+    }
+}
+
+/// Appends every following log line to a file, without colors, in place of the file
+/// set before
+pub fn set_log_file(path: &Path) -> Result<()> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open log file: {}", path.display()))?;
+    *LOG_FILE.lock().unwrap() = Some(file);
+    Ok(())
+}
+
+/// Stops appending log lines to the file set by `set_log_file`
+pub fn close_log_file() {
+    *LOG_FILE.lock().unwrap() = None;
+}
+
+/// Appends a line with a full UTC timestamp, whatever the console's style
+fn write_log_file(level: LogLevel, message: &str) {
+    let mut file = LOG_FILE.lock().unwrap();
+    let Some(file) = file.as_mut() else {
+        return;
+    };
+
+    // Messages may carry escape codes of their own, e.g. from a hook's error output
+    static ANSI: OnceLock<Regex> = OnceLock::new();
+    let ansi = ANSI.get_or_init(|| Regex::new(r"\x1b\[[0-9;]*[A-Za-z]").unwrap());
+
+    let timestamp = humantime::format_rfc3339_seconds(SystemTime::now());
+    let _ = writeln!(
+        file,
+        "{} [{}] {}",
+        timestamp,
+        level.label(),
+        ansi.replace_all(message, "")
+    );
+}
+
+/// Prints a line of the script's output, tagged so it stands apart from mlw's own logs
+/// and from the output of other scripts
+pub fn app_output(tag: &str, color: Color, line: &str, stderr: bool) {
+    let timestamp = humantime::format_rfc3339_seconds(SystemTime::now());
+    let prefix = format!("{} {}", timestamp, tag).color(color);
+    if stderr {
+        eprintln!("{} {}", prefix, line);
+    } else {
+        println!("{} {}", prefix, line);
     }
 }
@@ -1,23 +1,16 @@
-use std::{
-    fs,
-    path::Path,
-    process::{Child, Command, Stdio},
-    sync::{mpsc::channel, Arc, Mutex},
-    time::{Duration, Instant},
-};
+use std::{io::Read, path::Path, time::Duration};
 
 use anyhow::{Context, Result};
-use clap::Parser;
-use logger::{log, verbose_log, LogLevel};
-use notify::EventKind;
-use regex::Regex;
-use serde::Deserialize;
-use watcher::FileWatcher;
-
-mod logger;
-mod watcher;
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use colored::Colorize;
+use mlw::logger::{self, LogFormat, LogLevel};
+use mlw::{
+    check_config, events, generate_default_config, parse_config_profile, parse_config_str,
+    parse_duration, validate_config, ConfigFile, StringOrList, Watcher, DEFAULT_DELAY,
+    DEFAULT_PROFILE, SCRIPT_TYPES,
+};
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Clone, Debug)]
 #[command(
     name = "mlw",
     about = "A file watcher for multi languages",
@@ -25,254 +18,259 @@ mod watcher;
     author
 )]
 struct Cli {
-    /// Path to config file
-    #[arg(short, long, default_value = "mlw.toml")]
+    #[command(subcommand)]
+    command: Option<Commands>,
+
+    /// Path to config file, or - to read it from stdin
+    #[arg(short, long, default_value = "mlw.toml", global = true)]
     config: String,
 
+    /// Config profile to apply, from the `[profiles.<name>]` tables
+    #[arg(short, long, default_value = DEFAULT_PROFILE, global = true)]
+    profile: String,
+
     /// Generate a default config file
     #[arg(long, short)]
     gen_config: bool,
-}
 
-#[derive(Deserialize, Clone, Debug)]
-struct ConfigFile {
-    path: Vec<String>,
-    script_args: Option<Vec<String>>, // Added to support additional arguments
-    delay: u64,
-    verbose: Option<bool>,
-    ignore_pattern: Option<String>,
-    script_type: Option<String>,
-}
+    /// Overwrite an existing config file when generating one
+    #[arg(long, requires = "gen_config")]
+    force: bool,
 
-struct ScriptProcess {
-    child: Option<Child>,
-}
+    /// Run the script once without watching, exiting with its exit code
+    #[arg(long)]
+    once: bool,
 
-const DEFAULT_CONFIG: &str = r#"
-# Default mlw configuration file
-# Path(s) to watch
-path = ["./src"]
+    /// Path to watch, overriding the config (repeatable, works without a config file)
+    #[arg(short, long = "watch", value_name = "PATH")]
+    watch: Vec<String>,
 
-# Delay (in seconds) between script restarts
-delay = 2
+    /// Type of script to run, overriding the config
+    #[arg(long)]
+    script_type: Option<String>,
 
-# Verbose logging
-verbose = true
+    /// Delay between restarts, e.g. 2 or 500ms, overriding the config
+    #[arg(long, value_parser = parse_duration)]
+    delay: Option<Duration>,
 
-# Pattern for files to ignore (optional)
-ignore_pattern = ".*\\.git.*"
+    /// Log more detail, overriding the config (-v for debug, -vv for trace)
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
 
-# Type of script to run (e.g. python, node, go)
-script_type = "node"
+    /// Only print errors from mlw itself, the script's output is unaffected
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
 
+    /// Pattern for files to ignore, replacing the config's (repeatable)
+    #[arg(long, value_name = "PATTERN")]
+    ignore: Vec<String>,
 
-# Additional arguments for the script (optional)
-# script_args = ["--dev", "--watch"]
-"#;
+    /// Also append logs to this file, overriding the config
+    #[arg(long, value_name = "PATH")]
+    log_file: Option<String>,
 
-impl ScriptProcess {
-    fn new() -> Self {
-        Self { child: None }
-    }
+    /// List the supported script types and the commands they run
+    #[arg(long)]
+    list_types: bool,
 
-    fn stop(&mut self) {
-        if let Some(mut child) = self.child.take() {
-            let _ = child.kill();
-            let _ = child.wait();
-        }
-    }
+    /// Disable colored output (also disabled by NO_COLOR or when not a terminal)
+    #[arg(long)]
+    no_color: bool,
 
-    fn get_command_config(script_type: &str) -> Result<(&'static str, Vec<&'static str>)> {
-        match script_type {
-            // Interpreted languages
-            "python" => Ok(("python3", vec![])),
-            "python2" => Ok(("python2", vec![])),
-            "node" => Ok(("node", vec![])),
-            "lua" => Ok(("lua", vec![])),
-            "php" => Ok(("php", vec![])),
+    /// Log the commands that would run instead of running them, while still watching
+    #[arg(long)]
+    dry_run: bool,
 
-            // Compiled languages
-            "go" => Ok(("go", vec!["run"])),
-            "rust" => Ok(("cargo", vec!["run", "--"])),
+    /// Format of mlw's own log lines, the script's output is passed through as is
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
 
-            // shell
-            "sh" => Ok(("sh", vec![])),
+    /// Print the config with includes, the profile and CLI overrides applied, then exit.
+    /// Unset options are left out of TOML and null in JSON.
+    #[arg(long, value_enum, value_name = "FORMAT", num_args = 0..=1, default_missing_value = "toml")]
+    print_config: Option<ConfigFormat>,
 
-            unknown => anyhow::bail!("Unsupported script type: {}", unknown),
-        }
-    }
+    /// Log the resolved paths to watch and the filters applied to their changes at
+    /// startup, exiting afterwards with --dry-run
+    #[arg(long)]
+    list_watched: bool,
 
-    fn restart(&mut self, config: &ConfigFile) -> Result<()> {
-        self.stop();
+    /// Don't log the version, scripts and options when starting
+    #[arg(long)]
+    no_banner: bool,
 
-        let script_type = config
-            .script_type
-            .as_deref()
-            .ok_or_else(|| anyhow::anyhow!("Missing script type in config"))?;
-
-        let (command, default_args) = Self::get_command_config(script_type)?;
-
-        verbose_log(
-            LogLevel::Info,
-            &format!("Restarting script using: {}", command),
-            config.verbose,
-        );
-
-        for path in &config.path {
-            // Combine default arguments with user-provided arguments
-            let mut args = default_args.to_vec();
-            args.push(path.as_str());
-
-            // Add any additional arguments from config
-            if let Some(extra_args) = &config.script_args {
-                args.extend(extra_args.iter().map(String::as_str));
-            }
+    /// Stream lifecycle events as JSON lines to clients of this Unix socket
+    #[arg(long, value_name = "PATH")]
+    events_socket: Option<String>,
+}
 
-            verbose_log(
-                LogLevel::Debug,
-                &format!("Running command: {} with args: {:?}", command, args),
-                config.verbose,
-            );
+/// Formats `--print-config` can write the config in
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ConfigFormat {
+    Toml,
+    Json,
+}
 
-            let child = Command::new(command)
-                .args(&args)
-                .stdout(Stdio::inherit())
-                .stderr(Stdio::inherit())
-                .spawn()
-                .with_context(|| format!("Failed to start {} script", script_type))?;
+#[derive(Subcommand, Clone, Debug)]
+enum Commands {
+    /// Check the config and environment, printing a checklist of what passes and fails
+    Doctor,
+}
 
-            self.child = Some(child);
-        }
-        Ok(())
-    }
+/// Loads the config file, applying CLI overrides before validating the result.
+///
+/// Precedence is CLI > config file > built-in default.
+fn load_config(cli: &Cli) -> Result<ConfigFile> {
+    let config = read_config(cli)?;
+    validate_config(&config)?;
+    Ok(config)
 }
 
-fn load_config(file_path: &Path) -> Result<ConfigFile> {
-    let config_str = fs::read_to_string(file_path).context("Failed to read config file")?;
-    let config: ConfigFile = toml::from_str(&config_str).context("Failed to parse config file")?;
+/// Loads the config file with CLI overrides applied, without validating it
+fn read_config(cli: &Cli) -> Result<ConfigFile> {
+    // Paths given with -w let mlw run without a config file
+    let config_path = Path::new(&cli.config);
+    let mut config = if cli.config == STDIN_CONFIG {
+        let mut config_str = String::new();
+        std::io::stdin()
+            .read_to_string(&mut config_str)
+            .context("Failed to read config from stdin")?;
+        parse_config_str(&config_str, &cli.profile)?
+    } else if cli.watch.is_empty() || config_path.exists() {
+        parse_config_profile(config_path, &cli.profile)?
+    } else {
+        ConfigFile {
+            delay: DEFAULT_DELAY,
+            ..Default::default()
+        }
+    };
 
-    // Check if any paths exist
-    if config.path.is_empty() || !config.path.iter().all(|p| Path::new(p).exists()) {
-        anyhow::bail!("One or more specified paths do not exist");
+    if !cli.watch.is_empty() {
+        config.path = cli.watch.clone();
+        config.watch = None;
+    }
+    if let Some(script_type) = &cli.script_type {
+        config.script_type = Some(script_type.clone());
+    }
+    if let Some(delay) = cli.delay {
+        config.delay = delay;
+    }
+    match cli.verbose {
+        0 => {}
+        1 => config.log_level = Some(LogLevel::Debug),
+        _ => config.log_level = Some(LogLevel::Trace),
+    }
+    if !cli.ignore.is_empty() {
+        config.ignore_pattern = Some(StringOrList::Many(cli.ignore.clone()));
+    }
+    if let Some(log_file) = &cli.log_file {
+        config.log_file = Some(log_file.clone());
     }
-
     Ok(config)
 }
 
-fn handle_change(config: &ConfigFile, script_process: &mut ScriptProcess) -> Result<()> {
-    verbose_log(
-        LogLevel::Info,
-        "File change detected. Restarting...",
-        config.verbose,
-    );
-    std::thread::sleep(Duration::from_secs(config.delay));
-    script_process.restart(config)?;
-    verbose_log(
-        LogLevel::Info,
-        "script restarted successfully.",
-        config.verbose,
-    );
-    Ok(())
-}
-
-fn should_ignore_path(path: &Path, ignore_pattern: Option<&str>) -> bool {
-    ignore_pattern
-        .and_then(|pattern| Regex::new(pattern).ok())
-        .map(|regex| regex.is_match(&path.to_string_lossy()))
-        .unwrap_or(false)
-}
+/// Prints whether the config loads and passes each startup check, exiting with 1
+/// if anything failed
+fn doctor(cli: &Cli) -> ! {
+    let report = |passed: bool, description: &str, error: Option<&anyhow::Error>| {
+        let mark = if passed { "ok".green() } else { "fail".red() };
+        match error {
+            Some(e) => println!("[{}] {}: {:#}", mark, description, e),
+            None => println!("[{}] {}", mark, description),
+        }
+    };
 
-fn generate_default_config(output_path: &Path) -> Result<()> {
-    if output_path.exists() {
-        anyhow::bail!("Config file already exists at {:?}", output_path);
+    let config = match read_config(cli) {
+        Ok(config) => {
+            report(true, &format!("Config loads: {}", cli.config), None);
+            config
+        }
+        Err(e) => {
+            report(false, &format!("Config loads: {}", cli.config), Some(&e));
+            std::process::exit(1);
+        }
+    };
+
+    let mut failed = false;
+    for check in check_config(&config) {
+        failed |= check.result.is_err();
+        report(
+            check.result.is_ok(),
+            &check.description,
+            check.result.as_ref().err(),
+        );
     }
-
-    fs::write(output_path, DEFAULT_CONFIG).context("Failed to write config file")?;
-    println!("Default configuration file generated at {:?}", output_path);
-
-    Ok(())
+    std::process::exit(if failed { 1 } else { 0 });
 }
 
+/// `--config` value that reads the config from stdin
+const STDIN_CONFIG: &str = "-";
+
 fn main() -> Result<()> {
     let cli = Cli::parse();
+    logger::configure_color(cli.no_color);
+    logger::set_format(cli.log_format);
+    logger::set_quiet(cli.quiet);
+
+    if cli.list_types {
+        for (name, command, args) in SCRIPT_TYPES {
+            println!(
+                "{:<10} {}",
+                name,
+                [&[*command][..], args].concat().join(" ")
+            );
+        }
+        return Ok(());
+    }
+
+    if let Some(Commands::Doctor) = cli.command {
+        doctor(&cli);
+    }
 
     // Generate default config if the flag is set
     if cli.gen_config {
         let config_path = Path::new(&cli.config);
-        generate_default_config(config_path)?;
+        generate_default_config(config_path, cli.force)?;
         return Ok(());
     }
 
-    let config = load_config(Path::new(&cli.config))?;
-
-    if config.verbose.unwrap_or(false) {
-        log(LogLevel::Info, "Configuration loaded.");
+    if let Some(format) = cli.print_config {
+        let config = read_config(&cli)?;
+        match format {
+            ConfigFormat::Toml => print!(
+                "{}",
+                toml::to_string(&config).context("Failed to write config as TOML")?
+            ),
+            ConfigFormat::Json => println!(
+                "{}",
+                serde_json::to_string_pretty(&config).context("Failed to write config as JSON")?
+            ),
+        }
+        return Ok(());
     }
 
-    let (tx, rx) = channel();
-    let mut file_watcher = FileWatcher::new(tx)?;
-    for path in &config.path {
-        file_watcher.watch(Path::new(path))?;
+    if let Some(events_socket) = &cli.events_socket {
+        events::listen(Path::new(events_socket))?;
     }
 
-    let mut script_process = ScriptProcess::new();
-    script_process.restart(&config)?;
-
-    if config.verbose.unwrap_or(false) {
-        for path in &config.path {
-            log(LogLevel::Info, &format!("Watching path: {}", path));
-        }
+    let mut watcher = Watcher::new(load_config(&cli)?)
+        .dry_run(cli.dry_run)
+        .banner(!cli.no_banner)
+        .handle_ctrlc(true);
+    // Piped config can only be read once, so there's nothing to reload
+    if cli.config != STDIN_CONFIG {
+        let reload_cli = cli.clone();
+        watcher = watcher.reload_on_change(&cli.config, move || load_config(&reload_cli));
     }
 
-    let last_event_time = Arc::new(Mutex::new(Instant::now()));
-    loop {
-        match rx.recv() {
-            Ok(Ok(event)) => {
-                if let Some(path) = event.paths.first() {
-                    if should_ignore_path(path, config.ignore_pattern.as_deref()) {
-                        if config.verbose.unwrap_or(false) {
-                            log(LogLevel::Debug, &format!("Ignored file: {:?}", path));
-                        }
-                        continue;
-                    }
-
-                    if matches!(
-                        event.kind,
-                        EventKind::Modify(_) | EventKind::Create(_) | EventKind::Remove(_)
-                    ) {
-                        let now = Instant::now();
-                        let mut last_event_time = last_event_time.lock().unwrap();
-
-                        if now.duration_since(*last_event_time) > Duration::from_secs(config.delay)
-                        {
-                            *last_event_time = now; // Update the last event time
-
-                            if let Err(e) = handle_change(&config, &mut script_process) {
-                                log(LogLevel::Error, &format!("Error handling change: {}", e));
-                            }
-                        } else if config.verbose.unwrap_or(false) {
-                            log(LogLevel::Debug, "Ignoring event due to debounce");
-                        }
-                    }
-                }
-            }
-            Ok(Err(e)) => {
-                verbose_log(
-                    LogLevel::Error,
-                    &format!("Change handling error: {}", e),
-                    config.verbose,
-                );
-            }
-            Err(e) => {
-                verbose_log(
-                    LogLevel::Error,
-                    &format!("Failed to receive file event: {}", e),
-                    config.verbose,
-                );
-                break;
-            }
+    if cli.list_watched {
+        watcher.list_watched()?;
+        if cli.dry_run {
+            return Ok(());
         }
     }
-
-    Ok(())
+    if cli.once {
+        std::process::exit(watcher.run_once()?);
+    }
+    std::process::exit(watcher.run(())?)
 }
@@ -0,0 +1,10 @@
+use notify_rust::Notification;
+
+/// Shows a desktop notification, doing nothing if the platform has no notification backend
+pub fn notify(summary: &str, body: &str) {
+    let _ = Notification::new()
+        .appname("mlw")
+        .summary(summary)
+        .body(body)
+        .show();
+}
@@ -0,0 +1,1311 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    path::{Path, PathBuf},
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+#[cfg(unix)]
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+
+use anyhow::{Context, Result};
+use colored::Color;
+use notify::EventKind;
+
+use crate::config::{ConfigFile, WatchTarget};
+use crate::error::MlwError;
+use crate::events::{self, WatchEvent};
+use crate::handler::WatchHandler;
+use crate::logger::{self, log, verbose_log, LogLevel};
+use crate::notifier;
+use crate::runner::CommandRunner;
+
+/// Shell and flag that run a command line when `shell` is enabled
+#[cfg(not(windows))]
+pub(crate) const SHELL: (&str, &str) = ("sh", "-c");
+#[cfg(windows)]
+pub(crate) const SHELL: (&str, &str) = ("cmd", "/C");
+
+/// The file event that triggered a restart
+pub(crate) struct Change {
+    pub(crate) path: PathBuf,
+    pub(crate) kind: EventKind,
+}
+
+pub(crate) struct ScriptProcess {
+    /// Running children, each paired with the index of the target it belongs to
+    children: Vec<(usize, Child)>,
+    /// When each running child was started, by process id, used to enforce `run_timeout`
+    started: HashMap<u32, Instant>,
+    /// Children left in mlw's own process group, as interactive scripts are, by process
+    /// id. They are signalled alone, as they lead no group to signal.
+    ungrouped: HashSet<u32>,
+    /// When recent crashes happened, pruned to `CRASH_WINDOW`
+    crashes: Vec<Instant>,
+    /// Set once the script crashed more than `max_restarts` times in the window
+    gave_up: bool,
+    /// Threads reprinting piped child output, finished once the pipes close
+    output_threads: Vec<JoinHandle<()>>,
+    /// When the scripts were last started, used to enforce `cooldown_ms`
+    pub(crate) last_restart: Option<Instant>,
+    /// When the last restart began spawning the scripts, after any steps before them.
+    /// File events received since then are changes the new run may not have seen.
+    pub(crate) last_spawn: Option<Instant>,
+    /// Log commands instead of spawning them
+    pub(crate) dry_run: bool,
+    /// Spawns the commands once they are built
+    runner: Box<dyn CommandRunner>,
+}
+
+/// Built-in script types, each with the command it runs and that command's leading args
+pub const SCRIPT_TYPES: &[(&str, &str, &[&str])] = &[
+    // Interpreted languages
+    ("python", "python3", &[]),
+    ("python2", "python2", &[]),
+    ("node", "node", &[]),
+    ("lua", "lua", &[]),
+    ("php", "php", &[]),
+    ("ruby", "ruby", &[]),
+    ("perl", "perl", &[]),
+    ("elixir", "elixir", &[]),
+    // JavaScript / TypeScript runtimes
+    ("deno", "deno", &["run"]),
+    ("bun", "bun", &["run"]),
+    ("ts-node", "ts-node", &[]),
+    // Compiled languages
+    ("go", "go", &["run"]),
+    ("rust", "cargo", &["run", "--"]),
+    ("java", "java", &[]),
+    ("dotnet", "dotnet", &["run"]),
+    // shell
+    ("sh", "sh", &[]),
+];
+
+/// Seconds to wait after SIGTERM before killing the script
+const DEFAULT_STOP_TIMEOUT: u64 = 5;
+
+/// Attempts to start the script before giving up, covering e.g. ETXTBSY while a binary is replaced
+const DEFAULT_SPAWN_ATTEMPTS: u32 = 3;
+
+/// Wait before the first spawn retry, doubled after each one
+const SPAWN_RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+/// How long to wait for `wait_for_port` to be released before starting anyway
+const PORT_WAIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often to check whether `wait_for_port` is free
+const PORT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long a restart may take to pass its health check when `healthcheck_timeout_ms` is unset
+const DEFAULT_HEALTHCHECK_TIMEOUT_MS: u64 = 30_000;
+
+/// How often the health check is retried until it passes
+const HEALTHCHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Sliding window used to detect crash loops
+const CRASH_WINDOW: Duration = Duration::from_secs(10);
+
+impl ScriptProcess {
+    pub(crate) fn new(dry_run: bool, runner: Box<dyn CommandRunner>) -> Self {
+        Self {
+            children: Vec::new(),
+            started: HashMap::new(),
+            ungrouped: HashSet::new(),
+            crashes: Vec::new(),
+            gave_up: false,
+            output_threads: Vec::new(),
+            last_restart: None,
+            last_spawn: None,
+            dry_run,
+            runner,
+        }
+    }
+
+    /// Collects children that exited on their own, counting failures as crashes
+    pub(crate) fn reap_exited(&mut self, config: &ConfigFile, handler: &mut dyn WatchHandler) {
+        let mut crashed = 0;
+        let targets = config.targets();
+        self.children
+            .retain_mut(|(index, child)| match child.try_wait() {
+                Ok(Some(status)) => {
+                    let elapsed = self
+                        .started
+                        .remove(&child.id())
+                        .map(|started| format_elapsed(started.elapsed()));
+                    if status.success() {
+                        log(
+                            LogLevel::Info,
+                            &match elapsed {
+                                Some(elapsed) => {
+                                    format!("Script completed in {} (exit code 0)", elapsed)
+                                }
+                                None => "Script exited successfully (exit code 0)".to_string(),
+                            },
+                        );
+                    } else {
+                        crashed += 1;
+                        log(
+                            LogLevel::Error,
+                            &match elapsed {
+                                Some(elapsed) => format!(
+                                    "Script exited with {} after {}",
+                                    describe_exit(status),
+                                    elapsed
+                                ),
+                                None => format!("Script exited with {}", describe_exit(status)),
+                            },
+                        );
+                        let script = script_label(config, &targets[*index]);
+                        handler.on_crash(&script, &describe_exit(status));
+                        events::emit(WatchEvent::Crash {
+                            script,
+                            status: describe_exit(status),
+                        });
+                        if config.notify_desktop.unwrap_or(false) {
+                            notifier::notify(
+                                "mlw: script crashed",
+                                &format!(
+                                    "{} exited with {}",
+                                    script_label(config, &targets[*index]),
+                                    describe_exit(status)
+                                ),
+                            );
+                        }
+                    }
+                    false
+                }
+                Ok(None) => true,
+                Err(_) => false,
+            });
+
+        for _ in 0..crashed {
+            self.record_crash(config.max_restarts);
+        }
+    }
+
+    fn record_crash(&mut self, max_restarts: Option<u32>) {
+        let Some(max_restarts) = max_restarts else {
+            return;
+        };
+
+        let now = Instant::now();
+        self.crashes
+            .retain(|crash| now.duration_since(*crash) < CRASH_WINDOW);
+        self.crashes.push(now);
+
+        if self.crashes.len() > max_restarts as usize && !self.gave_up {
+            self.gave_up = true;
+            log(
+                LogLevel::Error,
+                &format!(
+                    "Script crashed {} times within {}s, not restarting until the crash is fixed",
+                    self.crashes.len(),
+                    CRASH_WINDOW.as_secs()
+                ),
+            );
+        }
+    }
+
+    /// Whether a run of the target is still going
+    pub(crate) fn is_running(&self, index: usize) -> bool {
+        self.children.iter().any(|(target, _)| *target == index)
+    }
+
+    /// Whether `max_restarts` gave up on a crash loop
+    pub(crate) fn gave_up(&self) -> bool {
+        self.gave_up
+    }
+
+    /// Whether a file change may restart the script.
+    ///
+    /// After giving up, changes arriving within `CRASH_WINDOW` of the last crash are
+    /// treated as fallout from the crash itself; a later change resets the counter.
+    pub(crate) fn allow_restart(&mut self) -> bool {
+        if !self.gave_up {
+            return true;
+        }
+
+        if self
+            .crashes
+            .last()
+            .is_some_and(|crash| crash.elapsed() < CRASH_WINDOW)
+        {
+            return false;
+        }
+
+        self.crashes.clear();
+        self.gave_up = false;
+        true
+    }
+
+    /// Waits until all piped output has been printed
+    pub(crate) fn flush_output(&mut self) {
+        for thread in self.output_threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+
+    /// Kills children that have been running for longer than `run_timeout`, leaving
+    /// them to be reaped as failures
+    pub(crate) fn kill_timed_out(&mut self, config: &ConfigFile) {
+        let Some(run_timeout) = config.run_timeout.map(Duration::from_secs) else {
+            return;
+        };
+        let targets = config.targets();
+        self.started
+            .retain(|pid, _| self.children.iter().any(|(_, child)| child.id() == *pid));
+
+        for (index, child) in &mut self.children {
+            let Some(started) = self.started.get(&child.id()) else {
+                continue;
+            };
+            if started.elapsed() < run_timeout || !matches!(child.try_wait(), Ok(None)) {
+                continue;
+            }
+            log(
+                LogLevel::Error,
+                &format!(
+                    "{} timed out after {:?}, killing it",
+                    script_label(config, &targets[*index]),
+                    run_timeout
+                ),
+            );
+            #[cfg(unix)]
+            Self::signal_group(child, &self.ungrouped, libc::SIGKILL);
+            #[cfg(windows)]
+            Self::kill_tree(child);
+            let _ = child.kill();
+            self.started.remove(&child.id());
+        }
+    }
+
+    /// Returns the first failing exit code once every child has exited, or 0 if all succeeded
+    pub(crate) fn try_wait_all(&mut self) -> Option<i32> {
+        let mut code = 0;
+        for (_, child) in &mut self.children {
+            let failed = match child.try_wait() {
+                Ok(Some(status)) if status.success() => None,
+                Ok(Some(status)) => Some(status.code().unwrap_or(1)),
+                Ok(None) => return None,
+                Err(_) => Some(1),
+            };
+            if let (0, Some(failed)) = (code, failed) {
+                code = failed;
+            }
+        }
+        Some(code)
+    }
+
+    /// Sends a signal to the process group led by the child, or to the child alone
+    /// when it is one of the `ungrouped`
+    #[cfg(unix)]
+    fn signal_group(child: &Child, ungrouped: &HashSet<u32>, signal: libc::c_int) {
+        let pid = child.id() as libc::pid_t;
+        // SAFETY: kill(2) and killpg(2) have no memory safety requirements
+        unsafe {
+            if ungrouped.contains(&child.id()) {
+                libc::kill(pid, signal);
+            } else {
+                libc::killpg(pid, signal);
+            }
+        }
+    }
+
+    /// Kills the child along with every process it started. Windows has no process
+    /// groups to signal, and `Child::kill` alone leaves the descendants running.
+    #[cfg(windows)]
+    fn kill_tree(child: &Child) {
+        let status = Command::new("taskkill")
+            .args(["/T", "/F", "/PID", &child.id().to_string()])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        if let Err(e) = status {
+            log(
+                LogLevel::Warn,
+                &format!("Failed to run taskkill for process {}: {}", child.id(), e),
+            );
+        }
+    }
+
+    /// Signals the running children of the targets (or all of them) in place of a restart.
+    ///
+    /// Returns false, signalling nothing, when a target has no live child to reload.
+    #[cfg(unix)]
+    pub(crate) fn signal_running(
+        &mut self,
+        config: &ConfigFile,
+        targets: Option<&[usize]>,
+        signal: libc::c_int,
+    ) -> bool {
+        let selected: Vec<usize> = (0..config.targets().len())
+            .filter(|index| targets.is_none_or(|targets| targets.contains(index)))
+            .collect();
+        let all_running = selected.iter().all(|selected| {
+            self.children
+                .iter_mut()
+                .any(|(index, child)| index == selected && matches!(child.try_wait(), Ok(None)))
+        });
+        if !all_running {
+            return false;
+        }
+
+        for (index, child) in &self.children {
+            if selected.contains(index) {
+                Self::signal_group(child, &self.ungrouped, signal);
+            }
+        }
+        true
+    }
+
+    /// Asks the children of a target (or all of them) to terminate with `stop_signal`,
+    /// killing any that outlive `stop_timeout`
+    pub(crate) fn stop(&mut self, targets: Option<&[usize]>, config: &ConfigFile) {
+        let timeout = Duration::from_secs(config.stop_timeout.unwrap_or(DEFAULT_STOP_TIMEOUT));
+        let (stopping, kept): (Vec<_>, Vec<_>) = self
+            .children
+            .drain(..)
+            .partition(|(index, _)| targets.is_none_or(|targets| targets.contains(index)));
+        self.children = kept;
+
+        #[cfg(unix)]
+        {
+            // Already checked by validate_config
+            let signal = config
+                .stop_signal
+                .as_deref()
+                .and_then(|name| parse_signal(name).ok())
+                .unwrap_or(libc::SIGTERM);
+            for (_, child) in &stopping {
+                Self::signal_group(child, &self.ungrouped, signal);
+            }
+        }
+
+        let deadline = Instant::now() + timeout;
+        for (_, mut child) in stopping {
+            if cfg!(unix) {
+                while Instant::now() < deadline {
+                    match child.try_wait() {
+                        Ok(None) => std::thread::sleep(Duration::from_millis(50)),
+                        _ => break,
+                    }
+                }
+            }
+            // Take down anything left in the group, e.g. the binary behind `cargo run`
+            #[cfg(unix)]
+            Self::signal_group(&child, &self.ungrouped, libc::SIGKILL);
+            #[cfg(windows)]
+            Self::kill_tree(&child);
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+
+    fn get_command_config(script_type: &str) -> Result<(&'static str, Vec<&'static str>)> {
+        match SCRIPT_TYPES
+            .iter()
+            .find(|(name, _, _)| *name == script_type)
+        {
+            Some((_, command, args)) => Ok((command, args.to_vec())),
+            None => {
+                let supported = SCRIPT_TYPES.iter().map(|(name, _, _)| *name).collect();
+                Err(MlwError::UnsupportedScriptType {
+                    name: script_type.to_string(),
+                    supported,
+                }
+                .into())
+            }
+        }
+    }
+
+    /// Guesses the script type from the extension of a file path
+    fn detect_script_type(path: &Path) -> Option<&'static str> {
+        if !path.is_file() {
+            return None;
+        }
+        match path.extension()?.to_str()? {
+            "py" => Some("python"),
+            "js" | "mjs" | "cjs" => Some("node"),
+            "lua" => Some("lua"),
+            "php" => Some("php"),
+            "rb" => Some("ruby"),
+            "pl" => Some("perl"),
+            "exs" => Some("elixir"),
+            "ts" => Some("ts-node"),
+            "java" => Some("java"),
+            "go" => Some("go"),
+            "rs" => Some("rust"),
+            "sh" => Some("sh"),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn resolve_command(
+        config: &ConfigFile,
+        target: &WatchTarget,
+    ) -> Result<(String, Vec<String>)> {
+        // The last of `commands` is the script, its command line already split
+        if let (None, Some(commands)) = (&target.script_type, &config.commands) {
+            let Some((command, args)) = commands.last().and_then(|last| last.split_first()) else {
+                anyhow::bail!("Commands are empty");
+            };
+            return Ok((command.clone(), args.to_vec()));
+        }
+
+        // A custom command takes precedence over the global script type lookup
+        if let (None, Some(command)) = (&target.script_type, &config.command) {
+            let args = config.command_args.clone().unwrap_or_default();
+            // The shell gets the command line as written
+            if !command.contains(char::is_whitespace) || config.shell.unwrap_or(false) {
+                return Ok((command.clone(), args));
+            }
+
+            // A whole command line, split the way a shell would, quotes included
+            let mut words = shell_words::split(command)
+                .with_context(|| format!("Failed to parse command: {}", command))?;
+            if words.is_empty() {
+                anyhow::bail!("Command is empty");
+            }
+            let command = words.remove(0);
+            words.extend(args);
+            return Ok((command, words));
+        }
+
+        let script_type = target
+            .script_type
+            .as_deref()
+            .or(config.script_type.as_deref())
+            .or_else(|| Self::detect_script_type(Path::new(&target.root())))
+            .ok_or_else(|| anyhow::anyhow!("Missing script type in config"))?;
+
+        let (command, default_args) = Self::get_command_config(script_type)?;
+        Ok((
+            command.to_string(),
+            default_args.iter().map(|arg| arg.to_string()).collect(),
+        ))
+    }
+
+    /// Spawns the command, retrying with exponential backoff while it fails
+    fn spawn_with_retry(
+        &mut self,
+        cmd: &mut Command,
+        command: &str,
+        config: &ConfigFile,
+    ) -> Result<Child> {
+        let attempts = config
+            .spawn_attempts
+            .unwrap_or(DEFAULT_SPAWN_ATTEMPTS)
+            .max(1);
+        let mut backoff = SPAWN_RETRY_BACKOFF;
+        let mut attempt = 1;
+        loop {
+            match self.runner.spawn(cmd) {
+                Ok(child) => return Ok(child),
+                Err(e) if attempt < attempts => {
+                    verbose_log(
+                        LogLevel::Warn,
+                        &format!(
+                            "Failed to start {} ({}), retrying in {:?} ({}/{})",
+                            command, e, backoff, attempt, attempts
+                        ),
+                        config.log_level(),
+                    );
+                    std::thread::sleep(backoff);
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                Err(source) if config.run_as_uid.is_some() || config.run_as_gid.is_some() => {
+                    return Err(MlwError::RunAsFailed {
+                        command: command.to_string(),
+                        uid: config.run_as_uid,
+                        gid: config.run_as_gid,
+                        source,
+                    }
+                    .into())
+                }
+                Err(source) => {
+                    return Err(MlwError::SpawnFailed {
+                        command: command.to_string(),
+                        attempts,
+                        source,
+                    }
+                    .into())
+                }
+            }
+        }
+    }
+
+    /// Restarts the scripts of the given targets, or of every target when `None`
+    pub(crate) fn restart(
+        &mut self,
+        config: &ConfigFile,
+        targets: Option<&[usize]>,
+        change: Option<&Change>,
+    ) -> Result<()> {
+        self.stop(targets, config);
+
+        if let Some(port) = config.wait_for_port {
+            wait_for_port(port, config);
+        }
+
+        // Re-read on every restart so edits to the file take effect
+        let env_file = config
+            .env_file
+            .as_deref()
+            .map(|path| load_env_file(Path::new(path)))
+            .transpose()?;
+
+        let all_targets = config.targets();
+        let selected = all_targets
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| targets.is_none_or(|targets| targets.contains(index)));
+
+        let changed_file = change.map(|change| change.path.to_string_lossy());
+
+        // The steps leading up to the last of `commands` run once, before any target starts
+        if let Some(commands) = &config.commands {
+            if selected
+                .clone()
+                .any(|(_, target)| target.script_type.is_none())
+            {
+                self.run_steps(commands, config, env_file.as_deref(), change)?;
+            }
+        }
+
+        self.last_spawn = Some(Instant::now());
+        for (index, watch_target) in selected {
+            let (command, default_args) = Self::resolve_command(config, watch_target)?;
+
+            verbose_log(
+                LogLevel::Info,
+                &format!("Restarting script using: {}", command),
+                config.log_level(),
+            );
+
+            // Combine default arguments with user-provided arguments, except for the last
+            // of `commands`, which runs as written
+            let root = watch_target.root();
+            let mut args: Vec<&str> = Vec::new();
+            // Passing the file that just changed, last and in place of the path, focuses e.g.
+            // a test runner on it
+            let focused_file = config
+                .pass_changed_file
+                .unwrap_or(false)
+                .then(|| {
+                    changed_file
+                        .as_deref()
+                        .or(config.changed_file_default.as_deref())
+                })
+                .flatten();
+            if config.commands.is_none() || watch_target.script_type.is_some() {
+                if focused_file.is_none() {
+                    args.push(root.as_str());
+                }
+
+                // Add any additional arguments from config
+                if let Some(extra_args) = watch_target
+                    .script_args
+                    .as_ref()
+                    .or(config.script_args.as_ref())
+                {
+                    args.extend(extra_args.iter().map(String::as_str));
+                }
+            }
+            args.extend(focused_file);
+
+            // A script type has no command line of its own, so with `shell` it is given the
+            // path and script_args the way a typed one would reference them
+            let from_script_type = watch_target.script_type.is_some()
+                || (config.command.is_none() && config.commands.is_none());
+            let (command, args) =
+                shell_command(config, &command, &default_args, &args, from_script_type);
+
+            verbose_log(
+                LogLevel::Debug,
+                &format!("Running command: {} with args: {:?}", command, args),
+                config.log_level(),
+            );
+
+            // Piped output is reprinted line by line with a timestamp and tag
+            let prefix_output = config.prefix_output.unwrap_or(false);
+            let output = || {
+                if prefix_output {
+                    Stdio::piped()
+                } else {
+                    Stdio::inherit()
+                }
+            };
+
+            let interactive = config.interactive.unwrap_or(false);
+            let stdin = if interactive {
+                Stdio::inherit()
+            } else {
+                Stdio::null()
+            };
+
+            let mut cmd = script_command(config, &command, &args, env_file.as_deref(), change);
+            cmd.stdin(stdin).stdout(output()).stderr(output());
+
+            // Run each script in its own process group so stop() reaches grandchildren.
+            // Interactive scripts stay in the foreground group, as reading the terminal
+            // from a background group would suspend them.
+            #[cfg(unix)]
+            if !interactive {
+                cmd.process_group(0);
+            }
+
+            if self.dry_run {
+                let env: Vec<String> = cmd
+                    .get_envs()
+                    .map(|(key, value)| {
+                        format!(
+                            "{}={}",
+                            key.to_string_lossy(),
+                            value.unwrap_or_default().to_string_lossy()
+                        )
+                    })
+                    .collect();
+                log(
+                    LogLevel::Info,
+                    &format!(
+                        "[dry-run] Would run: {} {:?} in {:?} with env {:?}",
+                        command,
+                        args,
+                        cmd.get_current_dir().unwrap_or(Path::new(".")),
+                        env
+                    ),
+                );
+                continue;
+            }
+
+            let spawned = Instant::now();
+            let mut child = self.spawn_with_retry(&mut cmd, &command, config)?;
+            let label = script_label(config, watch_target);
+
+            // Piped output tells when the script got going, otherwise spawning is all we see
+            self.output_threads.retain(|thread| !thread.is_finished());
+            if prefix_output {
+                let first_output = Arc::new(FirstOutput {
+                    label,
+                    spawned,
+                    seen: AtomicBool::new(false),
+                });
+                let tag = Arc::new(OutputTag::new(&all_targets, index)?);
+                if let Some(stdout) = child.stdout.take() {
+                    self.output_threads.push(forward_output(
+                        stdout,
+                        false,
+                        first_output.clone(),
+                        tag.clone(),
+                    ));
+                }
+                if let Some(stderr) = child.stderr.take() {
+                    self.output_threads
+                        .push(forward_output(stderr, true, first_output, tag));
+                }
+            } else {
+                log(
+                    LogLevel::Info,
+                    &format!("{} started in {}", label, format_elapsed(spawned.elapsed())),
+                );
+            }
+
+            self.started.insert(child.id(), spawned);
+            if interactive {
+                self.ungrouped.insert(child.id());
+            } else {
+                self.ungrouped.remove(&child.id());
+            }
+            self.children.push((index, child));
+        }
+        self.last_restart = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Runs every command of `commands` but the last, one after another, failing at the
+    /// first one that doesn't succeed
+    fn run_steps(
+        &mut self,
+        commands: &[Vec<String>],
+        config: &ConfigFile,
+        env_file: Option<&[(String, String)]>,
+        change: Option<&Change>,
+    ) -> Result<()> {
+        let total = commands.len();
+        for (number, step) in commands.iter().take(total.saturating_sub(1)).enumerate() {
+            let number = number + 1;
+            let Some((program, args)) = step.split_first() else {
+                continue;
+            };
+            let (program, args) = shell_command(config, program, args, &[] as &[&str], false);
+
+            if self.dry_run {
+                log(
+                    LogLevel::Info,
+                    &format!(
+                        "[dry-run] Would run step {}/{}: {} {:?}",
+                        number, total, program, args
+                    ),
+                );
+                continue;
+            }
+
+            verbose_log(
+                LogLevel::Info,
+                &format!(
+                    "Running step {}/{}: {}",
+                    number,
+                    total,
+                    shell_words::join(step)
+                ),
+                config.log_level(),
+            );
+            let mut cmd = script_command(config, &program, &args, env_file, change);
+            cmd.stdin(Stdio::null());
+            let status = self
+                .runner
+                .spawn(&mut cmd)
+                .and_then(|mut child| child.wait())
+                .with_context(|| format!("Failed to run step {}/{}: {}", number, total, program))?;
+            if !status.success() {
+                anyhow::bail!(
+                    "Step {}/{} failed with {}: {}",
+                    number,
+                    total,
+                    describe_exit(status),
+                    shell_words::join(step)
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The command to spawn for a command line, which with `shell` is the shell running it
+///
+/// The line is `program` followed by `line_args`. The `args` mlw adds, such as the path
+/// and script_args, become the shell's positional parameters (`"$1"`, `"$@"`) instead of
+/// being pasted onto the end of a line that may end in a pipe. `forward_args` appends
+/// `"$@"` to the line for commands that aren't written by hand.
+fn shell_command<S: AsRef<str>, T: AsRef<str>>(
+    config: &ConfigFile,
+    program: &str,
+    line_args: &[S],
+    args: &[T],
+    forward_args: bool,
+) -> (String, Vec<String>) {
+    let args = args.iter().map(|arg| arg.as_ref().to_string());
+    if !config.shell.unwrap_or(false) {
+        let line_args = line_args.iter().map(|arg| arg.as_ref().to_string());
+        return (program.to_string(), line_args.chain(args).collect());
+    }
+
+    let (shell, flag) = SHELL;
+    let mut line = program.to_string();
+    if !line_args.is_empty() {
+        line.push(' ');
+        line.push_str(&shell_words::join(line_args));
+    }
+    if cfg!(windows) {
+        // cmd has no positional parameters, so the arguments go on the end, quoted
+        let args: Vec<String> = args.collect();
+        if !args.is_empty() {
+            line.push(' ');
+            line.push_str(&shell_words::join(&args));
+        }
+        return (shell.to_string(), vec![flag.to_string(), line]);
+    }
+    if forward_args {
+        line.push_str(" \"$@\"");
+    }
+    // The name the shell takes as $0, before the positional parameters
+    let argv = [flag.to_string(), line, shell.to_string()];
+    (shell.to_string(), argv.into_iter().chain(args).collect())
+}
+
+/// Builds a command with the working directory, environment and user that the
+/// scripts and the steps before them run with
+fn script_command(
+    config: &ConfigFile,
+    program: &str,
+    args: &[String],
+    env_file: Option<&[(String, String)]>,
+    change: Option<&Change>,
+) -> Command {
+    let mut cmd = Command::new(program);
+    cmd.args(args);
+
+    if let Some(cwd) = &config.cwd {
+        cmd.current_dir(cwd);
+    }
+
+    // Let the script know what changed, left unset on the initial run
+    if let Some(change) = change {
+        cmd.env("MLW_CHANGED_FILE", &change.path)
+            .env("MLW_EVENT_KIND", event_kind_name(&change.kind));
+    }
+
+    // Layered on top of the inherited environment, inline `env` wins over `env_file`
+    if let Some(env_file) = env_file {
+        cmd.envs(env_file.iter().map(|(key, value)| (key, value)));
+    }
+    if let Some(env) = &config.env {
+        cmd.envs(env);
+    }
+
+    // The group goes first, as changing it needs the privileges the user drops
+    #[cfg(unix)]
+    {
+        if let Some(gid) = config.run_as_gid {
+            cmd.gid(gid);
+        }
+        if let Some(uid) = config.run_as_uid {
+            cmd.uid(uid);
+        }
+    }
+    cmd
+}
+
+/// Blocks until the `healthcheck` command succeeds and `healthcheck_url` answers,
+/// failing once `healthcheck_timeout_ms` passes. Passes right away when neither is set.
+pub(crate) fn wait_until_healthy(config: &ConfigFile, dry_run: bool) -> Result<()> {
+    if config.healthcheck.is_none() && config.healthcheck_url.is_none() {
+        return Ok(());
+    }
+    if dry_run {
+        log(LogLevel::Info, "[dry-run] Would wait for the health check");
+        return Ok(());
+    }
+
+    let timeout = Duration::from_millis(
+        config
+            .healthcheck_timeout_ms
+            .unwrap_or(DEFAULT_HEALTHCHECK_TIMEOUT_MS),
+    );
+    verbose_log(
+        LogLevel::Debug,
+        "Waiting for the health check to pass...",
+        config.log_level(),
+    );
+    let deadline = Instant::now() + timeout;
+    loop {
+        let command_passed = config
+            .healthcheck
+            .as_deref()
+            .is_none_or(|command| healthcheck_command_passes(command, config));
+        let url_passed = config
+            .healthcheck_url
+            .as_deref()
+            .is_none_or(|url| healthcheck_url_passes(url, HEALTHCHECK_INTERVAL));
+        if command_passed && url_passed {
+            verbose_log(LogLevel::Debug, "Health check passed", config.log_level());
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            anyhow::bail!("Health check did not pass within {:?}", timeout);
+        }
+        std::thread::sleep(HEALTHCHECK_INTERVAL);
+    }
+}
+
+/// Runs the health check command with its output discarded, as it's retried until it passes
+fn healthcheck_command_passes(command: &[String], config: &ConfigFile) -> bool {
+    let Some((program, args)) = command.split_first() else {
+        return true;
+    };
+    let mut cmd = Command::new(program);
+    cmd.args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    if let Some(cwd) = &config.cwd {
+        cmd.current_dir(cwd);
+    }
+    cmd.status().is_ok_and(|status| status.success())
+}
+
+/// Sends a plain HTTP GET, passing on any 2xx or 3xx status
+fn healthcheck_url_passes(url: &str, timeout: Duration) -> bool {
+    let Ok((host, port, path)) = parse_http_url(url) else {
+        return false;
+    };
+    let Some(address) = (host.as_str(), port)
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addresses| addresses.next())
+    else {
+        return false;
+    };
+    let Ok(mut stream) = TcpStream::connect_timeout(&address, timeout) else {
+        return false;
+    };
+    let _ = stream.set_read_timeout(Some(timeout));
+    let _ = stream.set_write_timeout(Some(timeout));
+    let request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path, host
+    );
+    if stream.write_all(request.as_bytes()).is_err() {
+        return false;
+    }
+    let mut status_line = String::new();
+    if BufReader::new(stream).read_line(&mut status_line).is_err() {
+        return false;
+    }
+    // e.g. "HTTP/1.1 200 OK"
+    status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse::<u16>().ok())
+        .is_some_and(|code| (200..400).contains(&code))
+}
+
+/// Splits an `http://host[:port][/path]` URL, the only kind `healthcheck_url` supports
+pub(crate) fn parse_http_url(url: &str) -> Result<(String, u16, String)> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| anyhow::anyhow!("Unsupported healthcheck_url, expected http://: {}", url))?;
+    let (authority, path) = match rest.find('/') {
+        Some(index) => (&rest[..index], &rest[index..]),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (
+            host,
+            port.parse()
+                .with_context(|| format!("Invalid port in healthcheck_url: {}", url))?,
+        ),
+        None => (authority, 80),
+    };
+    if host.is_empty() {
+        anyhow::bail!("Missing host in healthcheck_url: {}", url);
+    }
+    Ok((host.to_string(), port, path.to_string()))
+}
+
+/// Blocks until nothing is listening on `port`, so the new run can bind it
+fn wait_for_port(port: u16, config: &ConfigFile) {
+    let is_free = || TcpListener::bind(("127.0.0.1", port)).is_ok();
+    if is_free() {
+        return;
+    }
+
+    verbose_log(
+        LogLevel::Info,
+        &format!("Waiting for port {} to be released...", port),
+        config.log_level(),
+    );
+    let deadline = Instant::now() + PORT_WAIT_TIMEOUT;
+    while !is_free() {
+        if Instant::now() >= deadline {
+            verbose_log(
+                LogLevel::Warn,
+                &format!(
+                    "Port {} still in use after {:?}, starting anyway",
+                    port, PORT_WAIT_TIMEOUT
+                ),
+                config.log_level(),
+            );
+            return;
+        }
+        std::thread::sleep(PORT_POLL_INTERVAL);
+    }
+    verbose_log(
+        LogLevel::Debug,
+        &format!("Port {} is free", port),
+        config.log_level(),
+    );
+}
+
+/// Logs how long a run took to print its first line, shared by its stdout and stderr
+struct FirstOutput {
+    label: String,
+    spawned: Instant,
+    seen: AtomicBool,
+}
+
+impl FirstOutput {
+    fn record(&self) {
+        if !self.seen.swap(true, Ordering::Relaxed) {
+            log(
+                LogLevel::Info,
+                &format!(
+                    "{} started in {} (first output)",
+                    self.label,
+                    format_elapsed(self.spawned.elapsed())
+                ),
+            );
+        }
+    }
+}
+
+/// Colors cycled through for targets without a `color` of their own
+const OUTPUT_PALETTE: [Color; 6] = [
+    Color::Cyan,
+    Color::Magenta,
+    Color::Yellow,
+    Color::Green,
+    Color::Blue,
+    Color::Red,
+];
+
+/// The `[name]` tag and color a target's reprinted output lines start with
+struct OutputTag {
+    tag: String,
+    color: Color,
+}
+
+impl OutputTag {
+    /// Tags are padded to the longest one so that output lines up across targets,
+    /// and a lone unnamed target keeps the plain `[app]` tag
+    fn new(targets: &[WatchTarget], index: usize) -> Result<Self> {
+        let name = |target: &WatchTarget| match (&target.name, targets.len()) {
+            (Some(name), _) => name.clone(),
+            (None, 1) => "app".to_string(),
+            (None, _) => target.path.clone(),
+        };
+        let width = targets
+            .iter()
+            .map(|target| name(target).chars().count() + 2)
+            .max()
+            .unwrap_or_default();
+        let target = &targets[index];
+        let color = match &target.color {
+            Some(color) => parse_color(color)?,
+            None => OUTPUT_PALETTE[index % OUTPUT_PALETTE.len()],
+        };
+        Ok(Self {
+            tag: format!("{:<width$}", format!("[{}]", name(target))),
+            color,
+        })
+    }
+}
+
+/// Parses a color name as `colored` spells them, e.g. "green" or "bright blue"
+pub(crate) fn parse_color(color: &str) -> Result<Color> {
+    color
+        .parse::<Color>()
+        .map_err(|_| anyhow::anyhow!("Unknown color: {}", color))
+}
+
+/// Reprints each line of a child's output through the logger on a background thread
+fn forward_output(
+    reader: impl Read + Send + 'static,
+    stderr: bool,
+    first_output: Arc<FirstOutput>,
+    tag: Arc<OutputTag>,
+) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        for line in BufReader::new(reader).split(b'\n') {
+            let Ok(line) = line else {
+                break;
+            };
+            first_output.record();
+            logger::app_output(&tag.tag, tag.color, &String::from_utf8_lossy(&line), stderr);
+        }
+    })
+}
+
+/// Parses `KEY=VALUE` lines from a .env file, skipping blanks and `#` comments
+fn load_env_file(path: &Path) -> Result<Vec<(String, String)>> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read env file: {}", path.display()))?;
+
+    let mut vars = Vec::new();
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let Some((key, value)) = line.split_once('=') else {
+            anyhow::bail!("Invalid line in env file {}: {}", path.display(), line);
+        };
+
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+            .unwrap_or(value);
+        vars.push((key.trim().to_string(), value.to_string()));
+    }
+    Ok(vars)
+}
+
+/// Names a target's script by its script type, or its command when it has none
+pub(crate) fn script_label(config: &ConfigFile, target: &WatchTarget) -> String {
+    let label = target
+        .script_type
+        .as_deref()
+        .or(config.command.as_deref())
+        .or_else(|| {
+            let last = config.commands.as_ref()?.last()?;
+            last.first().map(String::as_str)
+        })
+        .or(config.script_type.as_deref())
+        .unwrap_or("script");
+    format!("{} ({})", label, target.path)
+}
+
+/// Milliseconds under a second, seconds with two decimals above
+fn format_elapsed(elapsed: Duration) -> String {
+    if elapsed < Duration::from_secs(1) {
+        format!("{}ms", elapsed.as_millis())
+    } else {
+        format!("{:.2}s", elapsed.as_secs_f64())
+    }
+}
+
+/// Formats an exit status as "exit code N", or the signal that killed the process
+fn describe_exit(status: ExitStatus) -> String {
+    if let Some(code) = status.code() {
+        return format!("exit code {}", code);
+    }
+
+    #[cfg(unix)]
+    if let Some(signal) = status.signal() {
+        return format!("signal {}", signal);
+    }
+
+    "unknown status".to_string()
+}
+
+/// Looks up a signal by name, with or without the `SIG` prefix
+#[cfg(unix)]
+pub(crate) fn parse_signal(name: &str) -> Result<libc::c_int> {
+    let upper = name.to_ascii_uppercase();
+    Ok(match upper.strip_prefix("SIG").unwrap_or(&upper) {
+        "TERM" => libc::SIGTERM,
+        "INT" => libc::SIGINT,
+        "HUP" => libc::SIGHUP,
+        "QUIT" => libc::SIGQUIT,
+        "USR1" => libc::SIGUSR1,
+        "USR2" => libc::SIGUSR2,
+        "KILL" => libc::SIGKILL,
+        _ => anyhow::bail!(
+            "Unsupported signal: {} (supported: SIGTERM, SIGINT, SIGHUP, SIGQUIT, SIGUSR1, SIGUSR2, SIGKILL)",
+            name
+        ),
+    })
+}
+
+/// Runs a hook command to completion, failing if it exits unsuccessfully
+pub(crate) fn run_hook(
+    name: &str,
+    hook: &[String],
+    config: &ConfigFile,
+    dry_run: bool,
+) -> Result<()> {
+    let Some((program, args)) = hook.split_first() else {
+        return Ok(());
+    };
+
+    if dry_run {
+        log(
+            LogLevel::Info,
+            &format!("[dry-run] Would run {} hook: {} {:?}", name, program, args),
+        );
+        return Ok(());
+    }
+
+    verbose_log(
+        LogLevel::Debug,
+        &format!("Running {} hook: {} with args: {:?}", name, program, args),
+        config.log_level(),
+    );
+
+    let mut cmd = Command::new(program);
+    cmd.args(args)
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit());
+    if let Some(cwd) = &config.cwd {
+        cmd.current_dir(cwd);
+    }
+
+    let status = cmd
+        .status()
+        .with_context(|| format!("Failed to run {} hook: {}", name, program))?;
+
+    if !status.success() {
+        anyhow::bail!("{} hook failed with {}", name, describe_exit(status));
+    }
+    Ok(())
+}
+
+/// Runs hooks given as command lines all at once, failing after they've all finished
+/// if any of them failed
+pub(crate) fn run_hooks_parallel(
+    name: &str,
+    hooks: &[String],
+    config: &ConfigFile,
+    dry_run: bool,
+) -> Result<()> {
+    let hooks = hooks
+        .iter()
+        .map(|hook| split_hook(hook))
+        .collect::<Result<Vec<_>>>()?;
+
+    let failures = std::thread::scope(|scope| {
+        let runs = hooks
+            .iter()
+            .map(|hook| scope.spawn(|| run_hook(name, hook, config, dry_run)))
+            .collect::<Vec<_>>();
+        runs.into_iter()
+            .zip(&hooks)
+            .filter_map(|(run, hook)| {
+                let result = run
+                    .join()
+                    .unwrap_or_else(|_| Err(anyhow::anyhow!("{} hook panicked", name)));
+                result.err().map(|e| (hook, e))
+            })
+            .collect::<Vec<_>>()
+    });
+
+    for (hook, e) in &failures {
+        log(
+            LogLevel::Error,
+            &format!("{:#}: {}", e, shell_words::join(*hook)),
+        );
+    }
+    if !failures.is_empty() {
+        anyhow::bail!(
+            "{} of {} {} hooks failed",
+            failures.len(),
+            hooks.len(),
+            name
+        );
+    }
+    Ok(())
+}
+
+/// Splits a hook's command line the way a shell would
+pub(crate) fn split_hook(hook: &str) -> Result<Vec<String>> {
+    let words =
+        shell_words::split(hook).with_context(|| format!("Failed to parse hook: {}", hook))?;
+    if words.is_empty() {
+        anyhow::bail!("Hook is empty");
+    }
+    Ok(words)
+}
+
+pub(crate) fn event_kind_name(kind: &EventKind) -> &'static str {
+    match kind {
+        EventKind::Create(_) => "create",
+        EventKind::Modify(_) => "modify",
+        EventKind::Remove(_) => "remove",
+        EventKind::Access(_) => "access",
+        EventKind::Any | EventKind::Other => "other",
+    }
+}
@@ -0,0 +1,20 @@
+use std::io;
+use std::process::{Child, Command};
+
+/// Starts the processes of the scripts, once their command line, environment and
+/// working directory are set up. Passed to `Watcher::runner`.
+///
+/// Implementing it lets tests see what mlw would run without running it, e.g. by
+/// recording `command` and spawning a stand-in process.
+pub trait CommandRunner: Send {
+    fn spawn(&mut self, command: &mut Command) -> io::Result<Child>;
+}
+
+/// Spawns the command as configured, the runner used unless another one is set
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn spawn(&mut self, command: &mut Command) -> io::Result<Child> {
+        command.spawn()
+    }
+}
@@ -0,0 +1,179 @@
+//! When a burst of file events turns into a restart, per the `restart_strategy`
+
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// How the restart window of a target is applied to its file events
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RestartStrategy {
+    /// Coalesce events for one window after the first, then restart
+    #[default]
+    Debounce,
+    /// Restart on the first event, but at most once per window
+    Throttle,
+    /// Restart on the first event, ignoring the window
+    Immediate,
+}
+
+/// Decides when a target's pending changes are due, remembering when it last restarted
+#[derive(Clone, Debug)]
+pub(crate) struct RestartSchedule {
+    strategy: RestartStrategy,
+    window: Duration,
+    last_restart: Option<Instant>,
+    /// The changed paths behind the last restart, sorted, empty when it had none
+    last_paths: Vec<PathBuf>,
+}
+
+impl RestartSchedule {
+    pub fn new(strategy: RestartStrategy, window: Duration) -> Self {
+        Self {
+            strategy,
+            window,
+            last_restart: None,
+            last_paths: Vec::new(),
+        }
+    }
+
+    /// When changes whose first event arrived at `first_event` should restart the
+    /// target. Events arriving until then are coalesced into the same restart.
+    pub fn deadline(&self, first_event: Instant) -> Instant {
+        match self.strategy {
+            RestartStrategy::Debounce => first_event + self.window,
+            RestartStrategy::Throttle => self.last_restart.map_or(first_event, |last_restart| {
+                (last_restart + self.window).max(first_event)
+            }),
+            RestartStrategy::Immediate => first_event,
+        }
+    }
+
+    /// Records that the target restarted, which throttling counts its window from
+    pub fn restarted(&mut self, at: Instant) {
+        self.last_restart = Some(at);
+        self.last_paths.clear();
+    }
+
+    /// Records that changes to `paths` restarted the target, for `is_repeat`
+    pub fn restarted_for(&mut self, at: Instant, paths: &[PathBuf]) {
+        self.restarted(at);
+        self.last_paths = sorted(paths);
+    }
+
+    /// Whether changes to `paths`, the first of them arriving at `first_event`, touch
+    /// exactly the files the last restart was for, within one window of it. Late events
+    /// for the files just saved then don't restart the target a second time, provided the
+    /// caller finds the files weren't written again since.
+    pub fn is_repeat(&self, paths: &[PathBuf], first_event: Instant) -> bool {
+        let Some(last_restart) = self.last_restart else {
+            return false;
+        };
+        !self.last_paths.is_empty()
+            && first_event < last_restart + self.window
+            && sorted(paths) == self.last_paths
+    }
+}
+
+fn sorted(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let mut paths = paths.to_vec();
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_millis(300);
+
+    fn paths(names: &[&str]) -> Vec<PathBuf> {
+        names
+            .iter()
+            .map(|name| PathBuf::from("src").join(name))
+            .collect()
+    }
+
+    #[test]
+    fn debounce_waits_one_window_after_the_first_change() {
+        let mut schedule = RestartSchedule::new(RestartStrategy::Debounce, WINDOW);
+        let now = Instant::now();
+        assert_eq!(schedule.deadline(now), now + WINDOW);
+
+        // A recent restart makes no difference
+        schedule.restarted(now);
+        let later = now + Duration::from_millis(50);
+        assert_eq!(schedule.deadline(later), later + WINDOW);
+    }
+
+    #[test]
+    fn throttle_restarts_at_most_once_per_window() {
+        let mut schedule = RestartSchedule::new(RestartStrategy::Throttle, WINDOW);
+        let now = Instant::now();
+        assert_eq!(schedule.deadline(now), now);
+
+        schedule.restarted(now);
+        let soon = now + Duration::from_millis(100);
+        assert_eq!(schedule.deadline(soon), now + WINDOW);
+
+        // Once the window has passed, the next change is due right away again
+        let later = now + WINDOW * 2;
+        assert_eq!(schedule.deadline(later), later);
+    }
+
+    #[test]
+    fn immediate_ignores_the_window() {
+        let mut schedule = RestartSchedule::new(RestartStrategy::Immediate, WINDOW);
+        let now = Instant::now();
+        assert_eq!(schedule.deadline(now), now);
+
+        schedule.restarted(now);
+        let soon = now + Duration::from_millis(1);
+        assert_eq!(schedule.deadline(soon), soon);
+    }
+
+    #[test]
+    fn strategies_parse_in_lowercase_with_debounce_by_default() {
+        assert_eq!(RestartStrategy::default(), RestartStrategy::Debounce);
+        let config: crate::ConfigFile =
+            toml::from_str("delay = 1\nrestart_strategy = \"throttle\"").unwrap();
+        assert_eq!(config.restart_strategy, Some(RestartStrategy::Throttle));
+    }
+
+    #[test]
+    fn same_files_within_the_window_repeat_the_restart() {
+        let mut schedule = RestartSchedule::new(RestartStrategy::Debounce, WINDOW);
+        let saved = Instant::now();
+        assert!(!schedule.is_repeat(&paths(&["a.rs", "b.rs", "c.rs"]), saved));
+
+        // The save restarts the target, then the formatter rewrites the same files in
+        // its own order, several of them more than once
+        let restarted = saved + WINDOW;
+        schedule.restarted_for(restarted, &paths(&["a.rs", "b.rs", "c.rs"]));
+        let formatted = restarted + Duration::from_millis(50);
+        assert!(schedule.is_repeat(&paths(&["c.rs", "a.rs", "b.rs", "a.rs"]), formatted));
+    }
+
+    #[test]
+    fn other_files_or_later_changes_restart_again() {
+        let mut schedule = RestartSchedule::new(RestartStrategy::Debounce, WINDOW);
+        let restarted = Instant::now();
+        schedule.restarted_for(restarted, &paths(&["a.rs", "b.rs"]));
+
+        let soon = restarted + Duration::from_millis(50);
+        assert!(!schedule.is_repeat(&paths(&["a.rs"]), soon));
+        assert!(!schedule.is_repeat(&paths(&["a.rs", "b.rs", "c.rs"]), soon));
+        assert!(!schedule.is_repeat(&paths(&["a.rs", "b.rs"]), restarted + WINDOW));
+    }
+
+    #[test]
+    fn restarts_without_changes_forget_the_files() {
+        let mut schedule = RestartSchedule::new(RestartStrategy::Debounce, WINDOW);
+        let restarted = Instant::now();
+        schedule.restarted_for(restarted, &paths(&["a.rs"]));
+        schedule.restarted(restarted);
+        assert!(!schedule.is_repeat(&paths(&["a.rs"]), restarted));
+    }
+}
@@ -1,23 +1,159 @@
 use anyhow::{Context, Result};
-use notify::{Config, Event, RecommendedWatcher, RecursiveMode, Watcher};
-use std::path::Path;
+use notify::{Config, Event, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
 use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+use crate::error::MlwError;
+
+/// A file event or watcher error, along with when it was received
+pub type ReceivedEvent = (Instant, notify::Result<Event>);
 
 pub struct FileWatcher {
-    watcher: RecommendedWatcher,
+    watcher: Box<dyn Watcher>,
+    poll_interval: Option<Duration>,
+    /// Directory actually watched for each requested path, shared when several map to one
+    watched: HashMap<PathBuf, (PathBuf, RecursiveMode)>,
 }
 
 impl FileWatcher {
-    pub fn new(tx: Sender<notify::Result<Event>>) -> Result<Self> {
-        let watcher = RecommendedWatcher::new(tx, Config::default())
-            .context("Failed to create file watcher")?;
-        Ok(Self { watcher })
+    /// Uses native OS events, or scans for changes when a poll interval is given
+    pub fn new(tx: Sender<ReceivedEvent>, poll_interval: Option<Duration>) -> Result<Self> {
+        Ok(Self {
+            watcher: open(tx, poll_interval)?,
+            poll_interval,
+            watched: HashMap::new(),
+        })
+    }
+
+    /// Replaces a failed watcher with a new one sending to `tx`, watching the same
+    /// paths again. Directories that are gone are left for `lost` to report.
+    pub fn reopen(&mut self, tx: Sender<ReceivedEvent>) -> Result<()> {
+        let mut watcher = open(tx, self.poll_interval)?;
+        let mut dirs: Vec<(&PathBuf, RecursiveMode)> = Vec::new();
+        for (dir, mode) in self.watched.values() {
+            match dirs.iter_mut().find(|(watched, _)| *watched == dir) {
+                // A shared directory is watched recursively if any path wants it so
+                Some((_, watched_mode)) if *mode == RecursiveMode::Recursive => {
+                    *watched_mode = *mode
+                }
+                Some(_) => {}
+                None => dirs.push((dir, *mode)),
+            }
+        }
+        for (dir, mode) in dirs.into_iter().filter(|(dir, _)| dir.exists()) {
+            watcher
+                .watch(dir, mode)
+                .map_err(|source| MlwError::WatchFailed {
+                    path: dir.clone(),
+                    source,
+                })?;
+        }
+        self.watcher = watcher;
+        Ok(())
+    }
+
+    /// Watches a directory, or a file through its parent directory so that the watch
+    /// survives editors replacing the file on save. Events for the file's siblings
+    /// are left for the caller to filter out.
+    pub fn watch(&mut self, path: &Path, recursive: bool) -> Result<()> {
+        let (dir, mode) = if path.is_file() {
+            let file = std::fs::canonicalize(path)
+                .context(format!("Failed to watch path: {}", path.display()))?;
+            let parent = file.parent().unwrap_or(Path::new("/")).to_path_buf();
+            (parent, RecursiveMode::NonRecursive)
+        } else {
+            (normalize(path), recursive_mode(recursive))
+        };
+
+        // Watching an already watched directory again only matters to widen it
+        let shared = self.watched.values().any(|(watched, _)| *watched == dir);
+        if !shared || mode == RecursiveMode::Recursive {
+            self.watcher
+                .watch(&dir, mode)
+                .map_err(|source| MlwError::WatchFailed {
+                    path: path.to_path_buf(),
+                    source,
+                })?;
+        }
+        self.watched.insert(path.to_path_buf(), (dir, mode));
+        Ok(())
     }
 
-    pub fn watch(&mut self, path: &Path) -> Result<()> {
+    /// Watched directories that no longer exist, whose watches died with them
+    pub fn lost(&self) -> Vec<PathBuf> {
+        self.watched
+            .iter()
+            .filter(|(path, (dir, _))| normalize(path) == *dir && !path.exists())
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+
+    /// Watches a lost directory again once it has been recreated
+    pub fn rewatch(&mut self, path: &Path) -> Result<()> {
+        let Some((dir, mode)) = self.watched.get(path) else {
+            return Ok(());
+        };
         self.watcher
-            .watch(path, RecursiveMode::Recursive)
-            .context(format!("Failed to watch path: {}", path.display()))?;
+            .watch(dir, *mode)
+            .map_err(|source| MlwError::WatchFailed {
+                path: path.to_path_buf(),
+                source,
+            })?;
         Ok(())
     }
+
+    pub fn unwatch(&mut self, path: &Path) -> Result<()> {
+        let Some((dir, _)) = self.watched.remove(path) else {
+            return Ok(());
+        };
+        if self.watched.values().any(|(watched, _)| *watched == dir) {
+            return Ok(());
+        }
+        self.watcher
+            .unwatch(&dir)
+            .context(format!("Failed to unwatch path: {}", path.display()))?;
+        Ok(())
+    }
+}
+
+/// Drops the `.` components of a path. Events come back under the directory as
+/// watched, so watching `./src` would report `./src/main.rs` as `/project/./src/main.rs`.
+fn normalize(path: &Path) -> PathBuf {
+    let normalized: PathBuf = path
+        .components()
+        .filter(|component| *component != Component::CurDir)
+        .collect();
+    if normalized.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        normalized
+    }
+}
+
+fn recursive_mode(recursive: bool) -> RecursiveMode {
+    if recursive {
+        RecursiveMode::Recursive
+    } else {
+        RecursiveMode::NonRecursive
+    }
+}
+
+/// Creates the underlying watcher, native unless a poll interval is given
+fn open(tx: Sender<ReceivedEvent>, poll_interval: Option<Duration>) -> Result<Box<dyn Watcher>> {
+    let tx = move |event| {
+        let _ = tx.send((Instant::now(), event));
+    };
+    let watcher: Box<dyn Watcher> = match poll_interval {
+        Some(interval) => Box::new(
+            PollWatcher::new(tx, Config::default().with_poll_interval(interval))
+                .context("Failed to create polling file watcher")?,
+        ),
+        None => Box::new(
+            RecommendedWatcher::new(tx, Config::default())
+                .context("Failed to create file watcher")?,
+        ),
+    };
+    Ok(watcher)
 }
@@ -0,0 +1,63 @@
+//! Changes made while a restart is under way restart the script again only when the new
+//! run may have missed them
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::time::Duration;
+
+use common::{RecordingRunner, RunningWatcher, TempDir};
+use mlw::ConfigFile;
+
+fn config(dir: &TempDir) -> ConfigFile {
+    ConfigFile {
+        path: vec![dir.path().to_string_lossy().into_owned()],
+        command: Some("server".to_string()),
+        delay: Duration::from_millis(100),
+        run_on_start: Some(false),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn changes_after_the_spawn_restart_again() {
+    let dir = TempDir::new("after-spawn");
+    let config = ConfigFile {
+        after_restart: Some(vec!["sleep".to_string(), "1".to_string()]),
+        ..config(&dir)
+    };
+    let runner = RecordingRunner::default();
+    let watcher = RunningWatcher::start(config, &runner);
+
+    fs::write(dir.path().join("a.txt"), "a").unwrap();
+    assert_eq!(runner.wait_for(1, Duration::from_secs(5)).len(), 1);
+    // Saved while the after_restart hook runs, after the new run was spawned
+    std::thread::sleep(Duration::from_millis(300));
+    fs::write(dir.path().join("b.txt"), "b").unwrap();
+
+    let argv = runner.wait_for(2, Duration::from_secs(5));
+    assert_eq!(watcher.stop(), 0);
+    assert_eq!(argv.len(), 2, "{:?}", argv);
+}
+
+#[test]
+fn changes_before_the_spawn_are_covered_by_the_new_run() {
+    let dir = TempDir::new("before-spawn");
+    let config = ConfigFile {
+        before_restart: Some(vec!["sleep".to_string(), "1".to_string()]),
+        ..config(&dir)
+    };
+    let runner = RecordingRunner::default();
+    let watcher = RunningWatcher::start(config, &runner);
+
+    fs::write(dir.path().join("a.txt"), "a").unwrap();
+    // Saved while the before_restart hook runs, so the new run starts with it
+    std::thread::sleep(Duration::from_millis(700));
+    fs::write(dir.path().join("b.txt"), "b").unwrap();
+
+    assert_eq!(runner.wait_for(1, Duration::from_secs(5)).len(), 1);
+    std::thread::sleep(Duration::from_secs(1));
+    assert_eq!(watcher.stop(), 0);
+    assert_eq!(runner.argv().len(), 1, "{:?}", runner.argv());
+}
@@ -0,0 +1,161 @@
+//! The command line each script type runs, recorded by a `CommandRunner` that starts a
+//! stand-in process instead of the script
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::time::Duration;
+
+use common::{RecordingRunner, RunningWatcher, TempDir};
+use mlw::{ConfigFile, WatchTarget, Watcher, SCRIPT_TYPES};
+
+fn target(path: &str, script_type: &str, script_args: Option<Vec<String>>) -> WatchTarget {
+    WatchTarget {
+        path: path.to_string(),
+        script_type: Some(script_type.to_string()),
+        script_args,
+        ..Default::default()
+    }
+}
+
+/// Runs every target once, returning the argv each one was started with
+fn run_once(config: ConfigFile) -> Vec<Vec<String>> {
+    mlw::logger::set_quiet(true);
+    let runner = RecordingRunner::default();
+    let code = Watcher::new(config)
+        .runner(runner.clone())
+        .run_once()
+        .unwrap();
+    assert_eq!(code, 0);
+    runner.argv()
+}
+
+/// Runs one target per script type, checking that each runs its command with the
+/// type's leading args, its path and then `script_args`
+fn assert_argv_for_each_script_type(script_args: Option<Vec<String>>) {
+    let mut targets = Vec::new();
+    let mut expected = Vec::new();
+    for (name, command, default_args) in SCRIPT_TYPES {
+        let path = format!("app.{}", name);
+        let argv: Vec<String> = std::iter::once(*command)
+            .chain(default_args.iter().copied())
+            .chain([path.as_str()])
+            .map(str::to_string)
+            .chain(script_args.iter().flatten().cloned())
+            .collect();
+        targets.push(target(&path, name, script_args.clone()));
+        expected.push(argv);
+    }
+
+    let config = ConfigFile {
+        watch: Some(targets),
+        ..Default::default()
+    };
+    assert_eq!(run_once(config), expected);
+}
+
+#[test]
+fn builds_argv_for_each_script_type() {
+    assert_argv_for_each_script_type(None);
+}
+
+#[test]
+fn appends_script_args_for_each_script_type() {
+    assert_argv_for_each_script_type(Some(vec!["--port".to_string(), "8080".to_string()]));
+}
+
+#[test]
+fn first_run_passes_changed_file_default_last_in_place_of_the_path() {
+    let config = ConfigFile {
+        path: vec!["tests".to_string()],
+        command: Some("pytest".to_string()),
+        script_args: Some(vec!["-x".to_string()]),
+        pass_changed_file: Some(true),
+        changed_file_default: Some("tests/test_app.py".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(
+        run_once(config),
+        [["pytest", "-x", "tests/test_app.py"].map(str::to_string)]
+    );
+}
+
+#[test]
+fn restarts_pass_the_changed_file_last() {
+    let dir = TempDir::new("changed-file");
+    let config = ConfigFile {
+        path: vec![dir.path().to_string_lossy().into_owned()],
+        command: Some("pytest".to_string()),
+        script_args: Some(vec!["-x".to_string()]),
+        pass_changed_file: Some(true),
+        delay: Duration::from_millis(100),
+        run_on_start: Some(false),
+        ..Default::default()
+    };
+    let runner = RecordingRunner::default();
+    let watcher = RunningWatcher::start(config, &runner);
+
+    let changed = dir.path().join("test_a.py");
+    fs::write(&changed, "def test_a(): pass\n").unwrap();
+    let argv = runner.wait_for(1, Duration::from_secs(5));
+    assert_eq!(watcher.stop(), 0);
+    assert_eq!(
+        argv.first().map(Vec::as_slice),
+        Some(
+            [
+                "pytest".to_string(),
+                "-x".to_string(),
+                changed.to_string_lossy().into_owned()
+            ]
+            .as_slice()
+        )
+    );
+}
+
+#[test]
+fn shell_passes_the_path_and_script_args_as_positional_parameters() {
+    let config = ConfigFile {
+        path: vec!["./src".to_string()],
+        command: Some("echo build && echo start | tee server.log".to_string()),
+        script_args: Some(vec!["--port".to_string(), "8080".to_string()]),
+        shell: Some(true),
+        ..Default::default()
+    };
+    assert_eq!(
+        run_once(config),
+        [[
+            "sh",
+            "-c",
+            "echo build && echo start | tee server.log",
+            "sh",
+            "./src",
+            "--port",
+            "8080"
+        ]
+        .map(str::to_string)]
+    );
+}
+
+#[test]
+fn shell_forwards_the_arguments_of_a_script_type() {
+    let config = ConfigFile {
+        path: vec!["app.py".to_string()],
+        script_type: Some("python".to_string()),
+        shell: Some(true),
+        ..Default::default()
+    };
+    let (_, command, default_args) = SCRIPT_TYPES
+        .iter()
+        .find(|(name, _, _)| *name == "python")
+        .unwrap();
+    let line = std::iter::once(*command)
+        .chain(default_args.iter().copied())
+        .chain([r#""$@""#])
+        .collect::<Vec<_>>()
+        .join(" ");
+    assert_eq!(
+        run_once(config),
+        [["sh", "-c", &line, "sh", "app.py"].map(str::to_string)]
+    );
+}
@@ -0,0 +1,60 @@
+//! `commands` runs its steps in order before starting the last one as the script
+#![cfg(unix)]
+
+mod common;
+
+use common::RecordingRunner;
+use mlw::{ConfigFile, Watcher};
+
+fn commands(commands: &[&[&str]]) -> Vec<Vec<String>> {
+    commands
+        .iter()
+        .map(|command| command.iter().map(|arg| arg.to_string()).collect())
+        .collect()
+}
+
+#[test]
+fn runs_steps_in_order_then_the_script_as_written() {
+    mlw::logger::set_quiet(true);
+    let steps = commands(&[
+        &["npm", "run", "generate"],
+        &["npm", "run", "build"],
+        &["node", "dist/server.js"],
+    ]);
+    let config = ConfigFile {
+        path: vec![".".to_string()],
+        script_args: Some(vec!["--ignored".to_string()]),
+        commands: Some(steps.clone()),
+        ..Default::default()
+    };
+
+    let runner = RecordingRunner::default();
+    let code = Watcher::new(config)
+        .runner(runner.clone())
+        .run_once()
+        .unwrap();
+    assert_eq!(code, 0);
+    assert_eq!(runner.argv(), steps);
+}
+
+#[test]
+fn failing_step_stops_the_restart() {
+    mlw::logger::set_quiet(true);
+    let config = ConfigFile {
+        path: vec![".".to_string()],
+        commands: Some(commands(&[&["false"], &["node", "dist/server.js"]])),
+        ..Default::default()
+    };
+
+    let runner = RecordingRunner::default();
+    let error = Watcher::new(config)
+        .runner(runner.clone())
+        .run_once()
+        .unwrap_err();
+    assert!(
+        error.to_string().starts_with("Step 1/2 failed"),
+        "{}",
+        error
+    );
+    assert_eq!(runner.argv(), commands(&[&["false"]]));
+}
@@ -0,0 +1,120 @@
+//! Helpers shared by the integration tests
+#![allow(dead_code)]
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use mlw::{CommandRunner, ConfigFile, MlwError, StopHandle, Watcher};
+
+/// Records the argv of every command and runs a stand-in process in its place: `false`
+/// for a program named `false`, so that the command fails, and `true` otherwise
+#[derive(Clone, Default)]
+pub struct RecordingRunner(Arc<Mutex<Vec<Vec<String>>>>);
+
+impl RecordingRunner {
+    /// The argv of every command started so far, in order
+    pub fn argv(&self) -> Vec<Vec<String>> {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// The argv of every command started so far, once there are at least `count` of
+    /// them, or whatever there is after `timeout`
+    pub fn wait_for(&self, count: usize, timeout: Duration) -> Vec<Vec<String>> {
+        let deadline = Instant::now() + timeout;
+        while self.0.lock().unwrap().len() < count && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        self.argv()
+    }
+}
+
+impl CommandRunner for RecordingRunner {
+    fn spawn(&mut self, command: &mut Command) -> io::Result<Child> {
+        let argv: Vec<String> = std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+        let stand_in = if argv[0] == "false" { "false" } else { "true" };
+        self.0.lock().unwrap().push(argv);
+        Command::new(stand_in).spawn()
+    }
+}
+
+/// A directory of its own for a test, removed on drop
+pub struct TempDir(pub PathBuf);
+
+impl TempDir {
+    pub fn new(test: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("mlw-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        // Events report canonical paths, e.g. under /private/tmp on macOS
+        Self(dir.canonicalize().unwrap())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A watcher running on a thread of its own with the recording runner, stopped and
+/// joined on drop
+pub struct RunningWatcher {
+    stop: StopHandle,
+    thread: Option<JoinHandle<Result<i32, MlwError>>>,
+}
+
+impl RunningWatcher {
+    /// Starts watching, returning once the watcher has had time to set up its watches
+    pub fn start(config: ConfigFile, runner: &RecordingRunner) -> Self {
+        mlw::logger::set_quiet(true);
+        let runner = runner.clone();
+        let (stop_tx, stop_rx) = channel();
+        let thread = std::thread::spawn(move || {
+            let watcher = Watcher::new(config).runner(runner);
+            stop_tx.send(watcher.stop_handle()).unwrap();
+            watcher.run(())
+        });
+        let stop = stop_rx.recv().unwrap();
+        std::thread::sleep(Duration::from_millis(500));
+        Self {
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    /// Stops the watcher, returning its exit code
+    pub fn stop(mut self) -> i32 {
+        self.join()
+    }
+
+    fn join(&mut self) -> i32 {
+        self.stop.stop();
+        match self.thread.take() {
+            Some(thread) => thread.join().unwrap().unwrap(),
+            None => 0,
+        }
+    }
+}
+
+impl Drop for RunningWatcher {
+    fn drop(&mut self) {
+        if self.thread.is_some() && !std::thread::panicking() {
+            self.join();
+        } else {
+            self.stop.stop();
+        }
+    }
+}
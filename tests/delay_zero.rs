@@ -0,0 +1,111 @@
+//! `delay = 0` restarts on every change, with no debounce window holding it back
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::io::Write;
+use std::sync::mpsc::{channel, Sender};
+use std::time::Duration;
+
+use common::RecordingRunner;
+use mlw::{ConfigFile, WatchHandler, Watcher};
+
+/// Reports each restart to the test
+struct Restarts(Sender<String>);
+
+impl WatchHandler for Restarts {
+    fn on_restart(&mut self, script: &str) {
+        let _ = self.0.send(script.to_string());
+    }
+}
+
+#[test]
+fn zero_delay_restarts_on_every_change() {
+    let dir = std::env::temp_dir().join(format!("mlw-delay-zero-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let config = ConfigFile {
+        path: vec![dir.to_string_lossy().into_owned()],
+        command: Some("true".to_string()),
+        delay: Duration::ZERO,
+        run_on_start: Some(false),
+        ..Default::default()
+    };
+    mlw::logger::set_quiet(true);
+
+    let (tx, rx) = channel();
+    let (stop_tx, stop_rx) = channel();
+    let watching = std::thread::spawn(move || {
+        let watcher = Watcher::new(config);
+        stop_tx.send(watcher.stop_handle()).unwrap();
+        watcher.run(Restarts(tx))
+    });
+    let stop = stop_rx.recv().unwrap();
+    std::thread::sleep(Duration::from_millis(500));
+
+    for round in 0..3 {
+        fs::write(dir.join("changed.txt"), round.to_string()).unwrap();
+        assert!(
+            rx.recv_timeout(Duration::from_secs(5)).is_ok(),
+            "change {} did not restart the script",
+            round
+        );
+        // Any further restarts for the same write don't count towards the next one
+        std::thread::sleep(Duration::from_millis(200));
+        rx.try_iter().for_each(drop);
+    }
+
+    stop.stop();
+    assert_eq!(watching.join().unwrap().unwrap(), 0);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn zero_delay_reloads_the_config_once_it_is_written() {
+    let dir = std::env::temp_dir().join(format!("mlw-delay-zero-config-{}", std::process::id()));
+    fs::create_dir_all(dir.join("src")).unwrap();
+    let dir = dir.canonicalize().unwrap();
+    let path = dir.join("mlw.toml");
+    let config = "delay = 0\npath = [\"src\"]\ncommand = \"server\"\nrun_on_start = false\n";
+    fs::write(&path, config.replace("server", "old-server")).unwrap();
+    mlw::logger::set_quiet(true);
+
+    let runner = RecordingRunner::default();
+    let (stop_tx, stop_rx) = channel();
+    let watching = std::thread::spawn({
+        let path = path.clone();
+        let runner = runner.clone();
+        move || {
+            let load = {
+                let path = path.clone();
+                move || Ok(mlw::parse_config(&path)?)
+            };
+            let watcher = Watcher::new(mlw::parse_config(&path).unwrap())
+                .runner(runner)
+                .reload_on_change(path, load);
+            stop_tx.send(watcher.stop_handle()).unwrap();
+            watcher.run(())
+        }
+    });
+    let stop = stop_rx.recv().unwrap();
+    std::thread::sleep(Duration::from_millis(500));
+
+    // An editor writing the new config in two parts, the first of them valid on its own
+    let mut file = fs::File::create(&path).unwrap();
+    file.write_all(config.as_bytes()).unwrap();
+    file.sync_all().unwrap();
+    std::thread::sleep(Duration::from_millis(20));
+    file.write_all(b"script_args = [\"--port\", \"8080\"]\n")
+        .unwrap();
+    drop(file);
+
+    std::thread::sleep(Duration::from_secs(1));
+    stop.stop();
+    assert_eq!(watching.join().unwrap().unwrap(), 0);
+    let src = dir.join("src").to_string_lossy().into_owned();
+    assert_eq!(
+        runner.argv(),
+        [["server", &src, "--port", "8080"].map(str::to_string)]
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,59 @@
+//! Configs embedded in `pyproject.toml` and `package.json` next to the project's own settings
+
+use std::fs;
+use std::path::PathBuf;
+
+use mlw::MlwError;
+
+/// A file with the given name and contents in a directory of its own, removed along
+/// with the directory on drop
+struct TempConfig(PathBuf);
+
+impl TempConfig {
+    fn new(test: &str, name: &str, contents: &str) -> Self {
+        let dir =
+            std::env::temp_dir().join(format!("mlw-embedded-{}-{}", test, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+        Self(path)
+    }
+}
+
+impl Drop for TempConfig {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(self.0.parent().unwrap());
+    }
+}
+
+#[test]
+fn reads_tool_mlw_from_pyproject() {
+    let file = TempConfig::new(
+        "pyproject",
+        "pyproject.toml",
+        "[project]\nname = \"demo\"\n\n[tool.mlw]\npath = [\".\"]\ndelay = 1\ncommand = \"pytest\"\n",
+    );
+    let config = mlw::parse_config(&file.0).unwrap();
+    assert_eq!(config.command.as_deref(), Some("pytest"));
+    assert_eq!(config.path, [file.0.parent().unwrap().to_string_lossy()]);
+}
+
+#[test]
+fn reads_the_mlw_key_from_package_json() {
+    let file = TempConfig::new(
+        "package",
+        "package.json",
+        r#"{"name": "demo", "mlw": {"path": ["."], "delay": "500ms", "command": "node"}}"#,
+    );
+    let config = mlw::parse_config(&file.0).unwrap();
+    assert_eq!(config.command.as_deref(), Some("node"));
+    assert_eq!(config.delay, std::time::Duration::from_millis(500));
+}
+
+#[test]
+fn pyproject_without_tool_mlw_is_an_error() {
+    let file = TempConfig::new("bare", "pyproject.toml", "[project]\nname = \"demo\"\n");
+    let error = mlw::parse_config(&file.0).unwrap_err();
+    assert!(matches!(error, MlwError::ConfigParse(_)), "{:?}", error);
+    assert!(format!("{:#}", error).contains("[tool.mlw]"), "{:#}", error);
+}
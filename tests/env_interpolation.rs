@@ -0,0 +1,82 @@
+//! Environment variables expand in the strings of a config once it's parsed
+
+use mlw::{ConfigFile, MlwError, StringOrList};
+
+fn parse(config: &str) -> Result<ConfigFile, MlwError> {
+    mlw::parse_config_str(&format!("delay = 1\n{}", config), mlw::DEFAULT_PROFILE)
+}
+
+#[test]
+fn expands_every_string_field() {
+    std::env::set_var("MLW_TEST_DIR", "/srv/app");
+    let config = parse(
+        r#"
+        path = ["$MLW_TEST_DIR/src"]
+        command = "node"
+        env_file = "$MLW_TEST_DIR/.env"
+        log_file = "${MLW_TEST_DIR}/mlw.log"
+        before_restart = ["make", "-C", "$MLW_TEST_DIR"]
+        healthcheck_url = "http://localhost:${MLW_TEST_PORT:-3000}/health"
+        ignore_glob = "$MLW_TEST_DIR/target/**"
+        ignore_pattern = ['\.log$$']
+
+        [env]
+        HOME_DIR = "$MLW_TEST_DIR"
+
+        [[watch]]
+        path = "$MLW_TEST_DIR/lib"
+        name = "lib-${MLW_TEST_NAME:-default}"
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(config.path, ["/srv/app/src"]);
+    assert_eq!(config.env_file.as_deref(), Some("/srv/app/.env"));
+    assert_eq!(config.log_file.as_deref(), Some("/srv/app/mlw.log"));
+    assert_eq!(config.before_restart.unwrap(), ["make", "-C", "/srv/app"]);
+    assert_eq!(
+        config.healthcheck_url.as_deref(),
+        Some("http://localhost:3000/health")
+    );
+    assert_eq!(
+        config.ignore_glob,
+        Some(StringOrList::One("/srv/app/target/**".to_string()))
+    );
+    assert_eq!(
+        config.ignore_pattern,
+        Some(StringOrList::Many(vec![r"\.log$".to_string()]))
+    );
+    assert_eq!(config.env.unwrap()["HOME_DIR"], "/srv/app");
+    let target = &config.watch.unwrap()[0];
+    assert_eq!(target.path, "/srv/app/lib");
+    assert_eq!(target.name.as_deref(), Some("lib-default"));
+}
+
+#[test]
+fn shell_commands_are_left_to_the_shell() {
+    let config = parse(
+        r#"
+        path = ["."]
+        shell = true
+        command = "for f in *.txt; do echo $f; done"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(
+        config.command.as_deref(),
+        Some("for f in *.txt; do echo $f; done")
+    );
+}
+
+#[test]
+fn undefined_variable_is_an_error() {
+    std::env::remove_var("MLW_TEST_UNSET");
+    let error = parse("path = [\".\"]\nenv_file = \"$MLW_TEST_UNSET/.env\"").unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .contains("Undefined environment variable in config: MLW_TEST_UNSET"),
+        "{}",
+        error
+    );
+}
@@ -0,0 +1,45 @@
+//! Failures come back as `MlwError` variants callers can match on
+
+use mlw::{ConfigFile, MlwError};
+
+#[test]
+fn invalid_toml_is_a_config_parse_error() {
+    let error = mlw::parse_config_str("path = [", mlw::DEFAULT_PROFILE).unwrap_err();
+    assert!(matches!(error, MlwError::ConfigParse(_)), "{:?}", error);
+}
+
+#[test]
+fn unknown_profile_is_a_config_parse_error() {
+    let error = mlw::parse_config_str("path = \".\"", "missing").unwrap_err();
+    assert!(matches!(error, MlwError::ConfigParse(_)), "{:?}", error);
+}
+
+#[test]
+fn missing_path_is_path_not_found() {
+    let missing = std::env::temp_dir().join(format!("mlw-missing-{}", std::process::id()));
+    let config = ConfigFile {
+        path: vec![missing.to_string_lossy().into_owned()],
+        script_type: Some("sh".to_string()),
+        ..Default::default()
+    };
+    match mlw::validate_config(&config) {
+        Err(MlwError::PathNotFound(path)) => assert_eq!(path, missing),
+        result => panic!("expected PathNotFound, got {:?}", result),
+    }
+}
+
+#[test]
+fn unknown_script_type_is_unsupported() {
+    let config = ConfigFile {
+        path: vec![".".to_string()],
+        script_type: Some("cobol".to_string()),
+        ..Default::default()
+    };
+    match mlw::validate_config(&config) {
+        Err(MlwError::UnsupportedScriptType { name, supported }) => {
+            assert_eq!(name, "cobol");
+            assert!(supported.contains(&"python"));
+        }
+        result => panic!("expected UnsupportedScriptType, got {:?}", result),
+    }
+}
@@ -0,0 +1,29 @@
+//! A `log_file` inside a watched path doesn't restart the scripts it logs about
+#![cfg(unix)]
+
+mod common;
+
+use std::time::Duration;
+
+use common::{RecordingRunner, RunningWatcher, TempDir};
+use mlw::ConfigFile;
+
+#[test]
+fn writes_to_the_log_file_are_not_changes() {
+    let dir = TempDir::new("log-file");
+    let config = ConfigFile {
+        path: vec![dir.path().to_string_lossy().into_owned()],
+        command: Some("server".to_string()),
+        log_file: Some(dir.path().join("mlw.log").to_string_lossy().into_owned()),
+        delay: Duration::from_millis(100),
+        ..Default::default()
+    };
+    let runner = RecordingRunner::default();
+    let watcher = RunningWatcher::start(config, &runner);
+
+    // Every restart would log, and so write to the file again
+    std::thread::sleep(Duration::from_secs(1));
+    assert_eq!(watcher.stop(), 0);
+    assert_eq!(runner.argv().len(), 1, "{:?}", runner.argv());
+    assert!(dir.path().join("mlw.log").exists());
+}
@@ -0,0 +1,51 @@
+//! A watched directory that's deleted and recreated restarts its target once it's back
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::time::Duration;
+
+use common::{RecordingRunner, RunningWatcher, TempDir};
+use mlw::ConfigFile;
+
+#[test]
+fn recreated_root_restarts_once() {
+    let dir = TempDir::new("lost-root");
+    let src = dir.path().join("src");
+    fs::create_dir(&src).unwrap();
+    let config = ConfigFile {
+        // The `.` is left out of the paths the script is told about
+        path: vec![dir
+            .path()
+            .join(".")
+            .join("src")
+            .to_string_lossy()
+            .into_owned()],
+        command: Some("server".to_string()),
+        pass_changed_file: Some(true),
+        delay: Duration::from_millis(100),
+        run_on_start: Some(false),
+        ..Default::default()
+    };
+    let runner = RecordingRunner::default();
+    let watcher = RunningWatcher::start(config, &runner);
+
+    fs::remove_dir_all(&src).unwrap();
+    assert_eq!(runner.wait_for(1, Duration::from_secs(5)).len(), 1);
+    std::thread::sleep(Duration::from_millis(500));
+
+    // Recreating it, as a branch switch would, and writing its files
+    fs::create_dir(&src).unwrap();
+    fs::write(src.join("main.rs"), "fn main() {}\n").unwrap();
+    assert_eq!(runner.wait_for(2, Duration::from_secs(5)).len(), 2);
+    std::thread::sleep(Duration::from_secs(1));
+    assert_eq!(watcher.stop(), 0);
+
+    let argv = runner.argv();
+    assert_eq!(argv.len(), 2, "{:?}", argv);
+    assert_eq!(
+        argv[1],
+        ["server".to_string(), src.to_string_lossy().into_owned()]
+    );
+}
@@ -0,0 +1,89 @@
+//! Late events for the files of a multi-file save don't restart the target twice, while
+//! saving them again does
+#![cfg(unix)]
+
+mod common;
+
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use common::{RecordingRunner, RunningWatcher, TempDir};
+use mlw::ConfigFile;
+
+/// Mirrors the cap on the paths a burst remembers, past which it never counts as a repeat
+const MAX_BURST_PATHS: usize = 1000;
+
+fn files(dir: &TempDir, count: usize) -> Vec<PathBuf> {
+    (0..count)
+        .map(|number| dir.path().join(format!("{}.rs", number)))
+        .collect()
+}
+
+/// Writes the files, their modification time set to `modified` when given
+fn save(files: &[PathBuf], modified: Option<SystemTime>) {
+    for path in files {
+        fs::write(path, "fn main() {}\n").unwrap();
+        if let Some(modified) = modified {
+            File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        }
+    }
+}
+
+/// Saves the files, waits for the restart, then writes them again as `rewrite` says,
+/// returning how many times the script was started
+fn restarts_after_rewrite(test: &str, count: usize, rewrite: Option<SystemTime>) -> usize {
+    let dir = TempDir::new(test);
+    let files = files(&dir, count);
+    let config = ConfigFile {
+        path: vec![dir.path().to_string_lossy().into_owned()],
+        command: Some("server".to_string()),
+        delay: Duration::from_millis(100),
+        // Long enough for the second write to land inside the window after the restart
+        debounce_ms: Some(1000),
+        run_on_start: Some(false),
+        ..Default::default()
+    };
+    let runner = RecordingRunner::default();
+    let watcher = RunningWatcher::start(config, &runner);
+
+    save(&files, None);
+    assert_eq!(runner.wait_for(1, Duration::from_secs(10)).len(), 1);
+    std::thread::sleep(Duration::from_millis(100));
+    save(&files, rewrite);
+
+    let argv = runner.wait_for(2, Duration::from_secs(3));
+    assert_eq!(watcher.stop(), 0);
+    argv.len()
+}
+
+/// A time well before the script was spawned, as late events for earlier writes have
+fn before_the_restart() -> Option<SystemTime> {
+    Some(SystemTime::now() - Duration::from_secs(60))
+}
+
+#[test]
+fn late_events_for_the_saved_files_are_repeats() {
+    assert_eq!(
+        restarts_after_rewrite("repeat-late", 3, before_the_restart()),
+        1
+    );
+}
+
+#[test]
+fn saving_the_same_files_again_restarts_again() {
+    assert_eq!(restarts_after_rewrite("repeat-saved", 3, None), 2);
+}
+
+#[test]
+fn capped_bursts_are_never_repeats() {
+    assert_eq!(
+        restarts_after_rewrite("repeat-capped", MAX_BURST_PATHS, before_the_restart()),
+        2
+    );
+}